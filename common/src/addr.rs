@@ -0,0 +1,52 @@
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+/// Append the default port to an address without a port.
+/// Accept `host`, `host:port`, IPv6 literals with or without brackets and a `%zone` suffix,
+/// and `[ipv6]:port`.
+pub fn with_port(addr: &str, port: u16) -> String {
+  let addr = addr.trim();
+  let unbracketed = addr
+    .strip_prefix('[')
+    .and_then(|a| a.strip_suffix(']'))
+    .unwrap_or(addr);
+  if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+    return SocketAddr::new(ip, port).to_string();
+  }
+  // scoped link-local addresses, e.g. `fe80::1%eth0`
+  if let Some((ip, zone)) = unbracketed.split_once('%') {
+    if ip.parse::<Ipv6Addr>().is_ok() && !zone.is_empty() {
+      return format!("[{}%{}]:{}", ip, zone, port);
+    }
+  }
+  if addr.contains(':') {
+    addr.to_string()
+  } else {
+    format!("{}:{}", addr, port)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_with_port() {
+    assert_eq!(with_port("steamdeck", 7777), "steamdeck:7777");
+    assert_eq!(with_port(" steamdeck:1234 ", 7777), "steamdeck:1234");
+    assert_eq!(with_port("192.168.1.2", 7777), "192.168.1.2:7777");
+    assert_eq!(with_port("192.168.1.2:1234", 7777), "192.168.1.2:1234");
+    assert_eq!(with_port("fe80::1", 7777), "[fe80::1]:7777");
+    assert_eq!(with_port("[fe80::1]", 7777), "[fe80::1]:7777");
+    assert_eq!(with_port("[fe80::1]:1234", 7777), "[fe80::1]:1234");
+    assert_eq!(with_port("::", 7777), "[::]:7777");
+    assert_eq!(with_port("fe80::1%eth0", 7777), "[fe80::1%eth0]:7777");
+    assert_eq!(with_port("[fe80::1%eth0]", 7777), "[fe80::1%eth0]:7777");
+    assert_eq!(
+      with_port("[fe80::1%eth0]:1234", 7777),
+      "[fe80::1%eth0]:1234"
+    );
+    // a numeric zone is a scope id
+    let addr: SocketAddr = with_port("fe80::1%2", 7777).parse().unwrap();
+    assert_eq!(addr.port(), 7777);
+  }
+}
@@ -0,0 +1,36 @@
+/// Keys pressed on the client, as a bit map.
+/// The server sends the whole state when it changes, like the mouse buttons.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keys(pub u8);
+
+impl Keys {
+  // bit map
+  pub const W: u8 = 1;
+  pub const A: u8 = 2;
+  pub const S: u8 = 4;
+  pub const D: u8 = 8;
+  pub const UP: u8 = 16;
+  pub const DOWN: u8 = 32;
+  pub const LEFT: u8 = 64;
+  pub const RIGHT: u8 = 128;
+
+  pub const ALL: [u8; 8] = [
+    Self::W,
+    Self::A,
+    Self::S,
+    Self::D,
+    Self::UP,
+    Self::DOWN,
+    Self::LEFT,
+    Self::RIGHT,
+  ];
+
+  /// Mark the key as pressed.
+  pub fn press(&mut self, key: u8) {
+    self.0 |= key;
+  }
+  /// Return if the key is pressed.
+  pub fn is_pressed(&self, key: u8) -> bool {
+    self.0 & key != 0
+  }
+}
@@ -1,7 +1,14 @@
+mod addr;
 mod gamepad;
+mod keyboard;
+pub mod metrics;
 mod mouse;
 mod packet;
 mod perf;
+mod uri;
 
+pub use addr::*;
+pub use keyboard::*;
 pub use mouse::*;
 pub use packet::*;
+pub use uri::*;
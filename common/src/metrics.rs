@@ -0,0 +1,237 @@
+//! Process-wide runtime metrics: counters and latency histograms, registered by name.
+//! Use [`counter!`](crate::counter) and [`perf!`](crate::perf) to record them,
+//! and [`snapshot`] to read them.
+
+use std::{
+  fmt,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+  },
+  time::Duration,
+};
+
+/// Upper bounds of the histogram buckets in microseconds. The last bucket counts the rest.
+pub const BUCKETS_US: [u64; 8] = [10, 50, 100, 500, 1_000, 5_000, 10_000, 50_000];
+
+/// A monotonically increasing counter.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+  pub fn add(&self, n: u64) {
+    self.0.fetch_add(n, Ordering::Relaxed);
+  }
+
+  pub fn get(&self) -> u64 {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+/// A latency histogram.
+#[derive(Debug, Default)]
+pub struct Histogram {
+  counts: [AtomicU64; BUCKETS_US.len() + 1],
+  sum_us: AtomicU64,
+  max_us: AtomicU64,
+}
+
+impl Histogram {
+  pub fn record(&self, elapsed: Duration) {
+    let us = elapsed.as_micros() as u64;
+    let bucket = BUCKETS_US
+      .iter()
+      .position(|&bound| us < bound)
+      .unwrap_or(BUCKETS_US.len());
+    self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+    self.sum_us.fetch_add(us, Ordering::Relaxed);
+    self.max_us.fetch_max(us, Ordering::Relaxed);
+  }
+
+  pub fn snapshot(&self) -> HistogramSnapshot {
+    HistogramSnapshot {
+      counts: std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed)),
+      sum_us: self.sum_us.load(Ordering::Relaxed),
+      max_us: self.max_us.load(Ordering::Relaxed),
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+  pub counts: [u64; BUCKETS_US.len() + 1],
+  pub sum_us: u64,
+  pub max_us: u64,
+}
+
+impl HistogramSnapshot {
+  pub fn count(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  pub fn mean_us(&self) -> u64 {
+    self.sum_us.checked_div(self.count()).unwrap_or(0)
+  }
+
+  /// An upper bound of the percentile (`0.0..=1.0`) in microseconds, from the bucket bounds.
+  pub fn percentile_us(&self, p: f64) -> u64 {
+    let target = (self.count() as f64 * p).ceil() as u64;
+    let mut seen = 0;
+    for (i, count) in self.counts.iter().enumerate() {
+      seen += count;
+      if seen >= target && seen > 0 {
+        return BUCKETS_US
+          .get(i)
+          .map_or(self.max_us, |&b| b.min(self.max_us));
+      }
+    }
+    0
+  }
+}
+
+impl fmt::Display for HistogramSnapshot {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "n={} mean={}us p50<={}us p99<={}us max={}us",
+      self.count(),
+      self.mean_us(),
+      self.percentile_us(0.5),
+      self.percentile_us(0.99),
+      self.max_us
+    )
+  }
+}
+
+struct Registry {
+  counters: Vec<(&'static str, &'static Counter)>,
+  histograms: Vec<(&'static str, &'static Histogram)>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+  counters: Vec::new(),
+  histograms: Vec::new(),
+});
+
+/// Get or register the counter with the name.
+/// Prefer [`counter!`](crate::counter) which caches the lookup.
+pub fn counter(name: &'static str) -> &'static Counter {
+  let mut registry = REGISTRY.lock().unwrap();
+  if let Some((_, counter)) = registry.counters.iter().find(|(n, _)| *n == name) {
+    return counter;
+  }
+  let counter = Box::leak(Box::default());
+  registry.counters.push((name, counter));
+  counter
+}
+
+/// Get or register the histogram with the name.
+/// Prefer [`perf!`](crate::perf) which caches the lookup.
+pub fn histogram(name: &'static str) -> &'static Histogram {
+  let mut registry = REGISTRY.lock().unwrap();
+  if let Some((_, histogram)) = registry.histograms.iter().find(|(n, _)| *n == name) {
+    return histogram;
+  }
+  let histogram = Box::leak(Box::default());
+  registry.histograms.push((name, histogram));
+  histogram
+}
+
+/// Values of all registered metrics, in the order they are registered.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+  pub counters: Vec<(&'static str, u64)>,
+  pub histograms: Vec<(&'static str, HistogramSnapshot)>,
+}
+
+impl Snapshot {
+  pub fn counter(&self, name: &str) -> Option<u64> {
+    self
+      .counters
+      .iter()
+      .find(|(n, _)| *n == name)
+      .map(|(_, v)| *v)
+  }
+}
+
+impl fmt::Display for Snapshot {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (name, value) in self.counters.iter() {
+      writeln!(f, "{}: {}", name, value)?;
+    }
+    for (name, histogram) in self.histograms.iter() {
+      writeln!(f, "{}: {}", name, histogram)?;
+    }
+    Ok(())
+  }
+}
+
+pub fn snapshot() -> Snapshot {
+  let registry = REGISTRY.lock().unwrap();
+  Snapshot {
+    counters: registry
+      .counters
+      .iter()
+      .map(|(name, counter)| (*name, counter.get()))
+      .collect(),
+    histograms: registry
+      .histograms
+      .iter()
+      .map(|(name, histogram)| (*name, histogram.snapshot()))
+      .collect(),
+  }
+}
+
+/// Get the registered counter with the name, e.g. `counter!("packets sent").add(1)`.
+#[macro_export]
+macro_rules! counter {
+  ($name:expr) => {{
+    static COUNTER: std::sync::OnceLock<&'static $crate::metrics::Counter> =
+      std::sync::OnceLock::new();
+    *COUNTER.get_or_init(|| $crate::metrics::counter($name))
+  }};
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_histogram() {
+    let histogram = Histogram::default();
+    assert_eq!(histogram.snapshot().percentile_us(0.99), 0);
+
+    for _ in 0..98 {
+      histogram.record(Duration::from_micros(20));
+    }
+    histogram.record(Duration::from_micros(700));
+    histogram.record(Duration::from_millis(80));
+
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.count(), 100);
+    assert_eq!(snapshot.counts[1], 98);
+    assert_eq!(snapshot.counts[BUCKETS_US.len()], 1);
+    assert_eq!(snapshot.mean_us(), (98 * 20 + 700 + 80_000) / 100);
+    assert_eq!(snapshot.percentile_us(0.5), 50);
+    assert_eq!(snapshot.percentile_us(0.99), 1_000);
+    assert_eq!(snapshot.percentile_us(1.0), 80_000);
+  }
+
+  #[test]
+  fn test_registry() {
+    counter!("test counter").add(2);
+    counter("test counter").add(1);
+    histogram("test histogram").record(Duration::from_micros(5));
+    assert!(std::ptr::eq(
+      histogram("test histogram"),
+      histogram("test histogram")
+    ));
+
+    let snapshot = snapshot();
+    assert_eq!(snapshot.counter("test counter"), Some(3));
+    assert_eq!(snapshot.counter("unknown"), None);
+    assert!(snapshot
+      .to_string()
+      .contains("test histogram: n=1 mean=5us p50<=5us p99<=5us max=5us"));
+  }
+}
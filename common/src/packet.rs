@@ -1,10 +1,29 @@
-use crate::mouse::Mouse;
+use crate::{keyboard::Keys, mouse::Mouse};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Packet<Gamepad> {
   Timestamp(u64),
-  Gamepad(Gamepad),
+  /// The state of the gamepad with the pad index.
+  Gamepad(u8, Gamepad),
   Mouse(Mouse),
+  /// The gamepad with the pad index is disconnected.
+  PadRemoved(u8),
+  /// The role of the receiving client. Sent before any input and when the role changes.
+  Role(Role),
+  /// The pressed keys, sent when they change.
+  Keys(Keys),
+}
+
+/// The role of a client connected to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+  /// The client applies the input. Only one client is the controller.
+  Controller,
+  /// The client receives the same stream but doesn't apply the input.
+  Observer,
 }
 
 pub const PACKET_FRAME_SIZE: usize = 16;
+/// Version of the packet format, increased on incompatible changes.
+/// The client and the server must use the same version.
+pub const PROTOCOL_VERSION: u32 = 3;
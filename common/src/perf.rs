@@ -1,17 +1,20 @@
-/// Check if the expression takes too long to execute in debug mode.
+/// Record how long the expression takes into the [`metrics`](crate::metrics) histogram with the name,
+/// and warn if it takes too long in debug mode.
 #[macro_export]
 macro_rules! perf {
-  ($name:expr, $e:expr, $threshold_ms:expr) => {
-    if log::log_enabled!(log::Level::Debug) {
-      let start = std::time::Instant::now();
-      let res = $e;
-      let elapsed = start.elapsed();
-      if elapsed > std::time::Duration::from_millis($threshold_ms) {
-        log::warn!("Performance warning({:?}): {:?}", $name, elapsed);
-      }
-      res
-    } else {
-      $e
+  ($name:expr, $e:expr, $threshold_ms:expr) => {{
+    static HISTOGRAM: std::sync::OnceLock<&'static $crate::metrics::Histogram> =
+      std::sync::OnceLock::new();
+    let histogram = *HISTOGRAM.get_or_init(|| $crate::metrics::histogram($name));
+    let start = std::time::Instant::now();
+    let res = $e;
+    let elapsed = start.elapsed();
+    histogram.record(elapsed);
+    if elapsed > std::time::Duration::from_millis($threshold_ms)
+      && log::log_enabled!(log::Level::Debug)
+    {
+      log::warn!("Performance warning({:?}): {:?}", $name, elapsed);
     }
-  };
+    res
+  }};
 }
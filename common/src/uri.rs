@@ -0,0 +1,115 @@
+use crate::PROTOCOL_VERSION;
+use std::{fmt, str::FromStr};
+
+/// A `stickdeck://host:port?v=<protocol>[&token=<token>]` URI to connect to a server,
+/// e.g. from a QR code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectUri {
+  /// `host:port`, IPv6 hosts are bracketed.
+  pub addr: String,
+  /// The protocol version of the server, see [`PROTOCOL_VERSION`].
+  pub version: Option<u32>,
+  /// A pairing token. It must be URI-safe as it's not percent-encoded.
+  pub token: Option<String>,
+}
+
+impl ConnectUri {
+  pub const SCHEME: &'static str = "stickdeck://";
+
+  /// A URI of the address with the current protocol version.
+  pub fn new(addr: impl Into<String>) -> Self {
+    Self {
+      addr: addr.into(),
+      version: Some(PROTOCOL_VERSION),
+      token: None,
+    }
+  }
+
+  /// Whether the string looks like a URI, so it should be parsed instead of used as an address.
+  pub fn is_uri(s: &str) -> bool {
+    s.len() >= Self::SCHEME.len() && s[..Self::SCHEME.len()].eq_ignore_ascii_case(Self::SCHEME)
+  }
+}
+
+impl fmt::Display for ConnectUri {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}{}", Self::SCHEME, self.addr)?;
+    let mut separator = '?';
+    if let Some(version) = self.version {
+      write!(f, "{}v={}", separator, version)?;
+      separator = '&';
+    }
+    if let Some(token) = &self.token {
+      write!(f, "{}token={}", separator, token)?;
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for ConnectUri {
+  type Err = String;
+
+  /// Parse a URI. Unknown query parameters are ignored.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+    if !Self::is_uri(s) {
+      return Err(format!("{} doesn't start with {}", s, Self::SCHEME));
+    }
+    let rest = &s[Self::SCHEME.len()..];
+    let (addr, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let addr = addr.trim_end_matches('/');
+    if addr.is_empty() {
+      return Err(format!("{} has no address", s));
+    }
+
+    let mut uri = Self {
+      addr: addr.into(),
+      version: None,
+      token: None,
+    };
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+      match param.split_once('=').unwrap_or((param, "")) {
+        ("v", v) => {
+          uri.version = Some(
+            v.parse()
+              .map_err(|_| format!("invalid protocol version {:?}", v))?,
+          )
+        }
+        ("token", token) => uri.token = Some(token.into()),
+        _ => {}
+      }
+    }
+    Ok(uri)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_uri() {
+    let uri = ConnectUri::new("[fe80::1]:7777");
+    assert_eq!(
+      uri.to_string(),
+      format!("stickdeck://[fe80::1]:7777?v={}", PROTOCOL_VERSION)
+    );
+    assert_eq!(uri.to_string().parse::<ConnectUri>().unwrap(), uri);
+
+    let uri: ConnectUri = "StickDeck://192.168.1.2:7777/?token=abc&x=1&v=1"
+      .parse()
+      .unwrap();
+    assert_eq!(uri.addr, "192.168.1.2:7777");
+    assert_eq!(uri.version, Some(1));
+    assert_eq!(uri.token.as_deref(), Some("abc"));
+    assert_eq!(
+      uri.to_string(),
+      "stickdeck://192.168.1.2:7777?v=1&token=abc"
+    );
+
+    assert!(!ConnectUri::is_uri("steamdeck:7777"));
+    assert!("steamdeck:7777".parse::<ConnectUri>().is_err());
+    assert!("stickdeck://?v=1".parse::<ConnectUri>().is_err());
+    assert!("stickdeck://deck?v=x".parse::<ConnectUri>().is_err());
+  }
+}
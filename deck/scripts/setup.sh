@@ -1,2 +1,2 @@
 #!/bin/bash
-konsole -e "/bin/bash -c 'mkdir -p /home/deck/.local/share/Steam/controller_config && cp ./stickdeck.vdf /home/deck/.local/share/Steam/controller_config/game_actions_480.vdf'"
+konsole -e "/bin/bash -c '(./stickdeck setup || true) && read -p \"Press enter to exit\"'"
@@ -0,0 +1,552 @@
+use crate::server::ClientInfo;
+use log::{debug, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+  io::{self, BufRead, BufReader, Read, Write},
+  net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+  sync::mpsc,
+  thread,
+  time::Duration,
+};
+use stickdeck_common::metrics;
+use tokio::sync::watch;
+
+/// The API listens on this address if `--api` is given without an address.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7778";
+const MAX_HEADER_SIZE: u64 = 8 * 1024;
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// Drop clients which don't send a full request in time.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The state of the app, published by the UI for `GET /status`.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Status {
+  pub started: bool,
+  pub reverse: bool,
+  /// The address the server is listening at, or the client address in reverse mode.
+  pub address: Option<String>,
+  pub profile: String,
+  pub profiles: Vec<String>,
+  pub input_update_interval_ms: u64,
+  pub fixed_rate: bool,
+  /// Whether the input thread has applied the latest settings.
+  pub settings_applied: bool,
+  /// Handles of the connected controllers.
+  pub controllers: Vec<u64>,
+  pub clients: Vec<ClientStatus>,
+  /// The last error shown in the UI.
+  pub error: Option<String>,
+  /// The last connection error of the server.
+  pub server_error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClientStatus {
+  pub id: u64,
+  pub address: String,
+  /// `Controller` or `Observer`.
+  pub role: String,
+  pub connected_secs: u64,
+  pub packets_sent: u64,
+  pub bytes_sent: u64,
+  pub queued: usize,
+}
+
+impl From<&ClientInfo> for ClientStatus {
+  fn from(client: &ClientInfo) -> Self {
+    Self {
+      id: client.id,
+      address: client.addr.to_string(),
+      role: format!("{:?}", client.role),
+      connected_secs: client.connected_at.elapsed().as_secs(),
+      packets_sent: client.packets_sent,
+      bytes_sent: client.bytes_sent,
+      queued: client.queued,
+    }
+  }
+}
+
+/// A running API, see [`spawn`].
+pub struct Api<M> {
+  pub addr: SocketAddr,
+  /// Messages posted to `/message`, to be handled by the UI.
+  pub message_rx: mpsc::Receiver<M>,
+  /// The UI publishes its status here.
+  pub status_tx: watch::Sender<Status>,
+}
+
+/// Serve the HTTP/JSON API at the address in new threads:
+/// - `GET /status`: the [`Status`] published by the UI.
+/// - `GET /metrics`: the runtime metrics.
+/// - `POST /message`: a JSON message for the UI, e.g. `"StartServer"` or `{"SelectProfile": "Game"}`.
+///
+/// Requests from browsers are rejected, so web pages can't control the server:
+/// the `Host` must be a loopback address or the API address, there must be no `Origin`,
+/// and messages must be posted as `application/json`.
+///
+/// Return [`Err`] if the address can't be bound.
+pub fn spawn<M: DeserializeOwned + Send + 'static>(addr: &str) -> io::Result<Api<M>> {
+  let listener = TcpListener::bind(addr)?;
+  let addr = listener.local_addr()?;
+  if addr.ip().is_loopback() {
+    info!("API listening on http://{}", addr);
+  } else {
+    warn!(
+      "API listening on http://{}, anyone on the network can control the server",
+      addr
+    );
+  }
+
+  let (message_tx, message_rx) = mpsc::channel();
+  let (status_tx, status_rx) = watch::channel(Status::default());
+  thread::spawn(move || {
+    for stream in listener.incoming() {
+      let mut stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+          warn!("Failed to accept API connection: {}", e);
+          continue;
+        }
+      };
+      let message_tx = message_tx.clone();
+      let status_rx = status_rx.clone();
+      thread::spawn(move || {
+        if let Err(e) = handle(&mut stream, addr.ip(), &message_tx, &status_rx) {
+          debug!("Failed to handle API request: {}", e);
+        }
+      });
+    }
+  });
+
+  Ok(Api {
+    addr,
+    message_rx,
+    status_tx,
+  })
+}
+
+struct Request {
+  method: String,
+  /// The path without the query.
+  path: String,
+  host: Option<String>,
+  /// Browsers send an `Origin` with cross-origin requests.
+  origin: bool,
+  content_type: Option<String>,
+  body: Vec<u8>,
+}
+
+fn handle<M: DeserializeOwned>(
+  stream: &mut TcpStream,
+  local: IpAddr,
+  message_tx: &mpsc::Sender<M>,
+  status_rx: &watch::Receiver<Status>,
+) -> io::Result<()> {
+  stream.set_read_timeout(Some(READ_TIMEOUT))?;
+  let (code, body) = match read_request(stream) {
+    // a DNS rebinding page sends its own host name
+    Ok(request)
+      if !request
+        .host
+        .as_deref()
+        .is_some_and(|h| is_allowed_host(h, local)) =>
+    {
+      (403, json!({ "error": "the host is not allowed" }))
+    }
+    Ok(request) if request.origin => (
+      403,
+      json!({ "error": "requests from browsers are not allowed" }),
+    ),
+    Ok(request) => route(&request, message_tx, status_rx),
+    Err(e) => (400, json!({ "error": e.to_string() })),
+  };
+  write_response(stream, code, &body)
+}
+
+fn read_request(stream: &mut impl Read) -> io::Result<Request> {
+  let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+  let mut reader = BufReader::new(stream.take(MAX_HEADER_SIZE));
+  let mut line = String::new();
+  reader.read_line(&mut line)?;
+  let mut parts = line.split_whitespace();
+  let (method, target) = match (parts.next(), parts.next()) {
+    (Some(method), Some(target)) => (method.to_string(), target),
+    _ => return Err(invalid("invalid request line")),
+  };
+  let path = target.split('?').next().unwrap_or_default().to_string();
+
+  let mut content_length = 0;
+  let mut host = None;
+  let mut origin = false;
+  let mut content_type = None;
+  loop {
+    line.clear();
+    if reader.read_line(&mut line)? == 0 {
+      return Err(invalid("incomplete headers"));
+    }
+    let header = line.trim_end();
+    if header.is_empty() {
+      break;
+    }
+    if let Some((name, value)) = header.split_once(':') {
+      let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+      match name.as_str() {
+        "content-length" => {
+          content_length = value
+            .parse()
+            .map_err(|_| invalid("invalid content length"))?;
+        }
+        "host" => host = Some(value.to_string()),
+        "origin" => origin = true,
+        "content-type" => content_type = Some(value.to_string()),
+        _ => {}
+      }
+    }
+  }
+  if content_length > MAX_BODY_SIZE {
+    return Err(invalid("body too large"));
+  }
+
+  // part of the body may be buffered already, which doesn't count towards the limit
+  reader.get_mut().set_limit(content_length as u64);
+  let mut body = vec![0; content_length];
+  reader.read_exact(&mut body)?;
+
+  Ok(Request {
+    method,
+    path,
+    host,
+    origin,
+    content_type,
+    body,
+  })
+}
+
+/// Whether the `Host` header, with or without a port, is a loopback address or the API address.
+fn is_allowed_host(host: &str, local: IpAddr) -> bool {
+  let name = match host.strip_prefix('[') {
+    Some(rest) => rest.split(']').next().unwrap_or_default(),
+    None => host.split(':').next().unwrap_or_default(),
+  };
+  name.eq_ignore_ascii_case("localhost")
+    || name
+      .parse::<IpAddr>()
+      .is_ok_and(|ip| ip.is_loopback() || (ip == local && !local.is_unspecified()))
+}
+
+/// Whether the body is JSON. Browsers can't post JSON cross-origin without a preflight.
+fn is_json(content_type: Option<&str>) -> bool {
+  content_type
+    .and_then(|t| t.split(';').next())
+    .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn route<M: DeserializeOwned>(
+  request: &Request,
+  message_tx: &mpsc::Sender<M>,
+  status_rx: &watch::Receiver<Status>,
+) -> (u16, Value) {
+  match (request.method.as_str(), request.path.as_str()) {
+    ("GET", "/status") => (
+      200,
+      serde_json::to_value(&*status_rx.borrow()).expect("status should be serializable"),
+    ),
+    ("GET", "/metrics") => (200, metrics_json(&metrics::snapshot())),
+    ("POST", "/message") if !is_json(request.content_type.as_deref()) => (
+      415,
+      json!({ "error": "the message must be posted as application/json" }),
+    ),
+    ("POST", "/message") => match serde_json::from_slice(&request.body) {
+      Ok(message) => match message_tx.send(message) {
+        Ok(()) => (202, json!({ "accepted": true })),
+        Err(_) => (503, json!({ "error": "the app is exiting" })),
+      },
+      Err(e) => (400, json!({ "error": format!("invalid message: {}", e) })),
+    },
+    (_, "/status" | "/metrics" | "/message") => (405, json!({ "error": "method not allowed" })),
+    _ => (404, json!({ "error": "not found" })),
+  }
+}
+
+fn metrics_json(snapshot: &metrics::Snapshot) -> Value {
+  let counters: Map<_, _> = snapshot
+    .counters
+    .iter()
+    .map(|(name, value)| (name.to_string(), json!(value)))
+    .collect();
+  let histograms: Map<_, _> = snapshot
+    .histograms
+    .iter()
+    .map(|(name, h)| {
+      (
+        name.to_string(),
+        json!({
+          "count": h.count(),
+          "mean_us": h.mean_us(),
+          "p50_us": h.percentile_us(0.5),
+          "p99_us": h.percentile_us(0.99),
+          "max_us": h.max_us,
+        }),
+      )
+    })
+    .collect();
+  json!({ "counters": counters, "histograms": histograms })
+}
+
+fn write_response(stream: &mut impl Write, code: u16, body: &Value) -> io::Result<()> {
+  let reason = match code {
+    200 => "OK",
+    202 => "Accepted",
+    400 => "Bad Request",
+    403 => "Forbidden",
+    404 => "Not Found",
+    405 => "Method Not Allowed",
+    415 => "Unsupported Media Type",
+    _ => "Service Unavailable",
+  };
+  let body = body.to_string();
+  write!(
+    stream,
+    "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    code,
+    reason,
+    body.len(),
+    body
+  )?;
+  stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{config::Config, input::Control, App, Flags, Message};
+  use iced::Application;
+  use serde::Deserialize;
+  use std::{env, fs, process};
+
+  #[derive(Deserialize, Debug, PartialEq)]
+  enum TestMessage {
+    StartServer,
+    SetInputUpdateInterval(u64),
+  }
+
+  /// Send a raw HTTP request with the extra headers, return the status code and the JSON body.
+  fn request_with(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &str,
+    body: &str,
+  ) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+      stream,
+      "{} {} HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+      method,
+      path,
+      headers,
+      body.len(),
+      body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let code = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (code, serde_json::from_str(body).unwrap())
+  }
+
+  /// Send a request like a script would.
+  fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+    let headers = "Host: localhost:7778\r\nContent-Type: application/json\r\n";
+    request_with(addr, method, path, headers, body)
+  }
+
+  #[test]
+  fn test_api() {
+    let api = spawn::<TestMessage>("127.0.0.1:0").unwrap();
+    let addr = api.addr;
+
+    api.status_tx.send_replace(Status {
+      started: true,
+      profile: "Game".into(),
+      ..Default::default()
+    });
+    let (code, status) = request(addr, "GET", "/status", "");
+    assert_eq!(code, 200);
+    assert_eq!(status["started"], true);
+    assert_eq!(status["profile"], "Game");
+
+    let (code, _) = request(addr, "POST", "/message", r#""StartServer""#);
+    assert_eq!(code, 202);
+    let (code, _) = request(
+      addr,
+      "POST",
+      "/message?x=1",
+      r#"{"SetInputUpdateInterval":5}"#,
+    );
+    assert_eq!(code, 202);
+    assert_eq!(
+      api.message_rx.try_iter().collect::<Vec<_>>(),
+      [
+        TestMessage::StartServer,
+        TestMessage::SetInputUpdateInterval(5)
+      ]
+    );
+
+    let (code, error) = request(addr, "POST", "/message", r#""Unknown""#);
+    assert_eq!(code, 400);
+    assert!(error["error"].as_str().unwrap().contains("invalid message"));
+
+    metrics::counter("api test").add(1);
+    let (code, metrics) = request(addr, "GET", "/metrics", "");
+    assert_eq!(code, 200);
+    assert_eq!(metrics["counters"]["api test"], 1);
+
+    assert_eq!(request(addr, "POST", "/status", "").0, 405);
+    assert_eq!(request(addr, "GET", "/", "").0, 404);
+  }
+
+  #[test]
+  fn test_reject_browsers() {
+    let api = spawn::<TestMessage>("127.0.0.1:0").unwrap();
+    let addr = api.addr;
+    let post =
+      |headers: &str| request_with(addr, "POST", "/message", headers, r#""StartServer""#).0;
+
+    // a form or `fetch` without a preflight
+    assert_eq!(post("Host: localhost\r\nContent-Type: text/plain\r\n"), 415);
+    assert_eq!(post("Host: localhost\r\n"), 415);
+    // a cross-origin request
+    let origin =
+      "Host: localhost\r\nOrigin: http://example.com\r\nContent-Type: application/json\r\n";
+    assert_eq!(post(origin), 403);
+    // DNS rebinding
+    let rebinding = "Host: example.com:7778\r\nContent-Type: application/json\r\n";
+    assert_eq!(post(rebinding), 403);
+    assert_eq!(
+      request_with(addr, "GET", "/status", "Host: example.com\r\n", "").0,
+      403
+    );
+    assert_eq!(request_with(addr, "GET", "/status", "", "").0, 403);
+    assert!(api.message_rx.try_recv().is_err());
+
+    // loopback hosts
+    for host in ["127.0.0.1:7778", "[::1]:7778", "LOCALHOST"] {
+      let headers = format!("Host: {}\r\n", host);
+      assert_eq!(request_with(addr, "GET", "/status", &headers, "").0, 200);
+    }
+    let json = "Host: 127.0.0.1\r\nContent-Type: application/json; charset=utf-8\r\n";
+    assert_eq!(post(json), 202);
+  }
+
+  #[test]
+  fn test_app_messages() {
+    // the messages of the app, so renaming them doesn't break the API silently
+    let messages = [
+      (r#""StartServer""#, "StartServer"),
+      (r#""StopServer""#, "StopServer"),
+      (r#""Exit""#, "Exit"),
+      (r#"{"SelectProfile":"Game"}"#, r#"SelectProfile("Game")"#),
+      (
+        r#"{"SetInputUpdateInterval":5}"#,
+        "SetInputUpdateInterval(5)",
+      ),
+      (r#"{"SetFixedRate":true}"#, "SetFixedRate(true)"),
+      (r#"{"SetDarkMode":false}"#, "SetDarkMode(false)"),
+      (r#"{"SelectController":"Auto"}"#, "SelectController(Auto)"),
+      (r#"{"SetController":2}"#, "SetController(2)"),
+      (r#"{"SetReverse":true}"#, "SetReverse(true)"),
+      (
+        r#"{"SetReverseAddress":"pc"}"#,
+        r#"SetReverseAddress("pc")"#,
+      ),
+      (r#""SubmitReverseAddress""#, "SubmitReverseAddress"),
+      (r#"{"SetGyroMode":"RightStick"}"#, "SetGyroMode(RightStick)"),
+      (
+        r#"{"SetTrackpadFilter":"OneEuro"}"#,
+        "SetTrackpadFilter(OneEuro)",
+      ),
+      (r#"{"SetMouseSensitivity":1.5}"#, "SetMouseSensitivity(1.5)"),
+      (r#"{"SetFlickStick":true}"#, "SetFlickStick(true)"),
+      (r#""CalibrateFlickStick""#, "CalibrateFlickStick"),
+      (r#"{"SetSteering":true}"#, "SetSteering(true)"),
+      (r#""RecenterSteering""#, "RecenterSteering"),
+      (
+        r#"{"SetLeftStickOutput":"Wasd"}"#,
+        "SetLeftStickOutput(Wasd)",
+      ),
+      (
+        r#"{"SetBind":{"bind":{"Address":"127.0.0.1"}}}"#,
+        "SetBind(BindOption { bind: Address(127.0.0.1), interface: None })",
+      ),
+    ];
+    let api = spawn::<Message>("127.0.0.1:0").unwrap();
+    for (json, _) in messages {
+      assert_eq!(
+        request(api.addr, "POST", "/message", json).0,
+        202,
+        "{}",
+        json
+      );
+    }
+    let received: Vec<_> = api
+      .message_rx
+      .try_iter()
+      .map(|m| format!("{:?}", m))
+      .collect();
+    let expected: Vec<_> = messages
+      .iter()
+      .map(|(_, debug)| debug.to_string())
+      .collect();
+    assert_eq!(received, expected);
+  }
+
+  #[test]
+  fn test_clamp_messages() {
+    let dir = env::temp_dir().join(format!("stickdeck-api-{}", process::id()));
+    let (control_tx, control_rx) = mpsc::channel();
+    let (_event_tx, event_rx) = mpsc::channel();
+    let (connected_tx, _connected_rx) = mpsc::channel();
+    let (_ui_tx, ui_rx) = watch::channel(Default::default());
+    let mut config = Config::default();
+    config.path = dir.join("config.json");
+    let (mut app, _) = App::new(Flags {
+      control_tx,
+      event_rx,
+      connected_tx,
+      ui_rx,
+      config,
+      config_error: None,
+      port: 7777,
+      connect: None,
+      api: None,
+    });
+
+    let api = spawn::<Message>("127.0.0.1:0").unwrap();
+    for json in [
+      r#"{"SetInputUpdateInterval":0}"#,
+      r#"{"SetMouseSensitivity":-1.0}"#,
+      r#"{"SetLockToLock":0.0}"#,
+    ] {
+      assert_eq!(request(api.addr, "POST", "/message", json).0, 202);
+    }
+    for message in api.message_rx.try_iter() {
+      let _ = app.update(message);
+    }
+
+    let profile = match control_rx.try_iter().last() {
+      Some(Control::Apply { profile, .. }) => profile,
+      control => panic!("unexpected control {:?}", control),
+    };
+    assert_eq!(profile.input_update_interval_ms, 1);
+    assert_eq!(profile.sensitivity.multiplier, 0.1);
+    assert_eq!(profile.steering.lock_to_lock, 30.0);
+    // the saved profile is clamped too
+    let saved = fs::read_to_string(dir.join("config.json")).unwrap();
+    assert!(saved.contains(r#""input_update_interval_ms": 1,"#));
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
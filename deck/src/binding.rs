@@ -0,0 +1,271 @@
+//! The default Steam Input configuration for the Steam Deck (`controller_neptune`),
+//! which binds the physical controls to the `XBoxControls` actions.
+
+use crate::vdf::{self, Value};
+
+/// Title of the generated configuration, used to recognize it when it's installed.
+pub const TITLE: &str = "StickDeck Default";
+
+const ACTION_SET: &str = "XBoxControls";
+
+/// A group of inputs with the same mode bound to one physical source.
+struct Group {
+  source: &'static str,
+  mode: &'static str,
+  /// The analog action driven by the group, if any.
+  analog: Option<&'static str>,
+  /// `(input, digital action)` pairs.
+  buttons: &'static [(&'static str, &'static str)],
+}
+
+const GROUPS: &[Group] = &[
+  Group {
+    source: "button_diamond",
+    mode: "four_buttons",
+    analog: None,
+    buttons: &[
+      ("button_A", "BtnA"),
+      ("button_B", "BtnB"),
+      ("button_X", "BtnX"),
+      ("button_Y", "BtnY"),
+    ],
+  },
+  Group {
+    source: "dpad",
+    mode: "dpad",
+    analog: None,
+    buttons: &[
+      ("dpad_north", "BtnUp"),
+      ("dpad_south", "BtnDown"),
+      ("dpad_east", "BtnRight"),
+      ("dpad_west", "BtnLeft"),
+    ],
+  },
+  Group {
+    source: "joystick",
+    mode: "joystick_move",
+    analog: Some("LeftMove"),
+    buttons: &[("click", "BtnLeftThumb")],
+  },
+  Group {
+    source: "right_joystick",
+    mode: "joystick_move",
+    analog: Some("RightMove"),
+    buttons: &[("click", "BtnRightThumb")],
+  },
+  Group {
+    source: "left_trigger",
+    mode: "trigger",
+    analog: Some("LeftTrigger"),
+    buttons: &[],
+  },
+  Group {
+    source: "right_trigger",
+    mode: "trigger",
+    analog: Some("RightTrigger"),
+    buttons: &[],
+  },
+  Group {
+    source: "right_trackpad",
+    mode: "absolute_mouse",
+    analog: Some("MouseMove"),
+    buttons: &[("click", "BtnLeftMouse")],
+  },
+  Group {
+    source: "left_trackpad",
+    mode: "absolute_mouse",
+    analog: Some("MouseScroll"),
+    buttons: &[("click", "BtnRightMouse")],
+  },
+  Group {
+    source: "gyro",
+    mode: "absolute_mouse",
+    analog: Some("GyroMove"),
+    buttons: &[],
+  },
+  Group {
+    source: "switch",
+    mode: "switches",
+    analog: None,
+    buttons: &[
+      ("button_escape", "BtnStart"),
+      ("button_menu", "BtnBack"),
+      ("left_bumper", "BtnLB"),
+      ("right_bumper", "BtnRB"),
+      ("button_back_left", "BtnPrecision"),
+      ("button_back_right", "BtnRecenter"),
+    ],
+  },
+];
+
+fn str(s: impl Into<String>) -> Value {
+  Value::Str(s.into())
+}
+
+fn obj<K: Into<String>>(entries: Vec<(K, Value)>) -> Value {
+  Value::Obj(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+}
+
+fn button(action: &str) -> Value {
+  obj(vec![(
+    "activators",
+    obj(vec![(
+      "Full_Press",
+      obj(vec![(
+        "bindings",
+        obj(vec![(
+          "binding",
+          str(format!("game_action {} {}, , ", ACTION_SET, action)),
+        )]),
+      )]),
+    )]),
+  )])
+}
+
+fn group(id: usize, group: &Group) -> Value {
+  let mut entries = vec![("id", str(id.to_string())), ("mode", str(group.mode))];
+  if let Some(action) = group.analog {
+    entries.push(("gameactions", obj(vec![(ACTION_SET, str(action))])));
+  }
+  entries.push((
+    "inputs",
+    obj(
+      group
+        .buttons
+        .iter()
+        .map(|(input, action)| (*input, button(action)))
+        .collect(),
+    ),
+  ));
+  obj(entries)
+}
+
+/// Generate the default configuration.
+pub fn generate() -> String {
+  let mut entries = vec![
+    ("version", str("3")),
+    ("revision", str("1")),
+    ("title", str(TITLE)),
+    (
+      "description",
+      str("Default StickDeck layout generated by `stickdeck setup`."),
+    ),
+    ("controller_type", str("controller_neptune")),
+    (
+      "actions",
+      obj(vec![(
+        ACTION_SET,
+        obj(vec![
+          ("title", str(format!("#Set_{}", ACTION_SET))),
+          ("legacy_set", str("0")),
+        ]),
+      )]),
+    ),
+  ];
+  entries.extend(
+    GROUPS
+      .iter()
+      .enumerate()
+      .map(|(id, g)| ("group", group(id, g))),
+  );
+  entries.push((
+    "preset",
+    obj(vec![
+      ("id", str("0")),
+      ("name", str(ACTION_SET)),
+      (
+        "group_source_bindings",
+        obj(
+          GROUPS
+            .iter()
+            .enumerate()
+            .map(|(id, g)| (id.to_string(), str(format!("{} active", g.source))))
+            .collect(),
+        ),
+      ),
+    ]),
+  ));
+
+  vdf::to_string(&obj(vec![("controller_mappings", obj(entries))]))
+}
+
+/// Return `true` if the content is a configuration generated by [`generate`] (of any version).
+pub fn is_generated(content: &str) -> bool {
+  vdf::parse(content)
+    .ok()
+    .and_then(|root| {
+      root
+        .get("controller_mappings")?
+        .get("title")?
+        .as_str()
+        .map(|title| title == TITLE)
+    })
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::setup::{action_names, ACTIONS_VDF};
+
+  #[test]
+  fn test_generated_layout() {
+    let content = generate();
+    assert!(is_generated(&content));
+
+    let actions = vdf::parse(ACTIONS_VDF).unwrap();
+    let actions = action_names(&actions).unwrap();
+    let root = vdf::parse(&content).unwrap();
+    let mappings = root.get("controller_mappings").unwrap();
+
+    let groups: Vec<_> = mappings
+      .entries()
+      .filter(|(k, _)| *k == "group")
+      .map(|(_, g)| g)
+      .collect();
+
+    let mut referenced = Vec::new();
+    for group in &groups {
+      if let Some(action) = group
+        .get("gameactions")
+        .and_then(|g| g.get(ACTION_SET))
+        .and_then(Value::as_str)
+      {
+        referenced.push(action.to_string());
+      }
+      for (_, input) in group.get("inputs").unwrap().entries() {
+        let binding = input
+          .get("activators")
+          .and_then(|a| a.get("Full_Press"))
+          .and_then(|a| a.get("bindings"))
+          .and_then(|b| b.get("binding"))
+          .and_then(Value::as_str)
+          .unwrap();
+        let action = binding
+          .strip_prefix(&format!("game_action {} ", ACTION_SET))
+          .and_then(|b| b.split(',').next())
+          .unwrap();
+        referenced.push(action.to_string());
+      }
+    }
+
+    for action in &referenced {
+      assert!(
+        actions.contains(&action.as_str()),
+        "unknown action {}",
+        action
+      );
+    }
+    // every action is bound
+    referenced.sort();
+    referenced.dedup();
+    assert_eq!(referenced.len(), actions.len());
+
+    // every group is active in the preset
+    let bindings = mappings
+      .get("preset")
+      .and_then(|p| p.get("group_source_bindings"))
+      .unwrap();
+    assert_eq!(bindings.entries().count(), groups.len());
+  }
+}
@@ -1,41 +1,441 @@
+use crate::{error::Error, network::Bind, profile::Profile};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use serde_json::{Map, Value};
+use std::{
+  collections::BTreeMap,
+  env, fs,
+  path::{Path, PathBuf},
+};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
 pub struct Config {
-  #[serde(default)]
+  /// Schema version, see [`MIGRATIONS`].
+  pub version: u32,
   pub dark: bool,
-  #[serde(default)]
-  pub input_update_interval_ms: u64,
+  /// Name of the profile in use, always one of [`Config::profiles`].
+  pub active_profile: String,
+  pub profiles: BTreeMap<String, Profile>,
+  /// Reverse mode: connect to the client (`stickdeck-win --listen`) instead of waiting for it.
+  pub reverse: bool,
+  /// The client address in reverse mode, `host` or `host:port`.
+  pub reverse_address: String,
+  /// Where the server listens.
+  pub bind: Bind,
+  /// Where the config is loaded from and saved to.
+  #[serde(skip)]
+  pub path: PathBuf,
+  /// The file at [`Config::path`] can't be read or backed up, so it must not be overwritten.
+  /// Saving fails until the app is restarted with a valid file.
+  #[serde(skip)]
+  read_only: bool,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
+      version: Self::VERSION,
       dark: true,
-      input_update_interval_ms: 3,
+      active_profile: Self::DEFAULT_PROFILE.into(),
+      profiles: BTreeMap::from([(Self::DEFAULT_PROFILE.into(), Profile::default())]),
+      reverse: false,
+      reverse_address: String::new(),
+      bind: Bind::Auto,
+      path: PathBuf::new(),
+      read_only: false,
     }
   }
 }
 
+/// `MIGRATIONS[v]` migrates a config from version `v` to `v + 1`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_v0, migrate_v1];
+
+/// Version 0 has no `version` field, and may store `0` as the input update interval.
+fn migrate_v0(config: &mut Map<String, Value>) {
+  if config.get("input_update_interval_ms") == Some(&Value::from(0)) {
+    config.remove("input_update_interval_ms");
+  }
+}
+
+/// Version 1 has no profiles, the settings are moved into the default profile.
+fn migrate_v1(config: &mut Map<String, Value>) {
+  let mut profile = Map::new();
+  if let Some(interval) = config.remove("input_update_interval_ms") {
+    profile.insert("input_update_interval_ms".into(), interval);
+  }
+  let mut profiles = Map::new();
+  profiles.insert(Config::DEFAULT_PROFILE.into(), profile.into());
+  config.insert("profiles".into(), profiles.into());
+  config.insert("active_profile".into(), Config::DEFAULT_PROFILE.into());
+}
+
 impl Config {
-  const FILENAME: &'static str = "config.json";
+  pub const VERSION: u32 = MIGRATIONS.len() as u32;
+  /// The config file used before the config was moved to [`Config::default_path`].
+  const LEGACY_PATH: &'static str = "config.json";
+  const DEFAULT_PROFILE: &'static str = "Default";
 
-  /// Try to load the config from `config.json`.
+  /// `$XDG_CONFIG_HOME/stickdeck/config.json`, or `~/.config/stickdeck/config.json`.
+  pub fn default_path() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+      .unwrap_or_default()
+      .join("stickdeck")
+      .join("config.json")
+  }
+
+  /// Try to load the config from the path, migrating the legacy `config.json` in the working directory.
   /// If the file does not exist, create a new one with the default values.
-  pub fn init() -> Self {
-    fs::read_to_string(Self::FILENAME)
-      .ok()
-      .and_then(|content| serde_json::from_str(&content).ok())
-      .unwrap_or_else(|| {
-        let config = Config::default();
-        config.save();
-        config
-      })
-  }
-
-  /// Save the config to `config.json`.
-  pub fn save(&self) {
-    fs::write(Self::FILENAME, serde_json::to_string_pretty(self).unwrap()).unwrap();
+  ///
+  /// The config is always returned. If the file is invalid, the default values are used
+  /// and the file is backed up before it's overwritten. If it can't be read or backed up,
+  /// the config is read-only. The error should be shown to the user.
+  pub fn init(path: PathBuf) -> (Self, Option<Error>) {
+    let legacy = Path::new(Self::LEGACY_PATH);
+    let source = if !path.exists() && legacy.is_file() {
+      info!("Migrating {} to {}", legacy.display(), path.display());
+      legacy.to_path_buf()
+    } else {
+      path.clone()
+    };
+
+    let (mut config, dirty, err) = match fs::read_to_string(&source) {
+      Ok(content) => match Self::parse(&content) {
+        Ok((config, migrated)) => (config, migrated || source != path, None),
+        Err(e) => {
+          let backup = backup_path(&source);
+          match fs::copy(&source, &backup) {
+            Ok(_) => {
+              let e = Error::Config(format!(
+                "{} is invalid ({}), using the default config. The invalid file is backed up to {}",
+                source.display(),
+                e,
+                backup.display()
+              ));
+              (Self::default(), true, Some(e))
+            }
+            // don't overwrite the only copy of the file
+            Err(copy_err) => {
+              let e = Error::Config(format!(
+                "{} is invalid ({}) and can't be backed up ({}), using the default config",
+                source.display(),
+                e,
+                copy_err
+              ));
+              (Self::read_only(), false, Some(e))
+            }
+          }
+        }
+      },
+      Err(_) if !source.exists() => (Self::default(), true, None),
+      Err(e) => (
+        Self::read_only(),
+        false,
+        Some(Error::Config(format!(
+          "Failed to read {}: {}",
+          source.display(),
+          e
+        ))),
+      ),
+    };
+
+    config.path = path;
+    let err = match (dirty, err) {
+      (true, err) => match (err, config.save().err()) {
+        (Some(Error::Config(e)), Some(save_err)) => {
+          Some(Error::Config(format!("{}. {}", e, save_err)))
+        }
+        (err, save_err) => err.or(save_err),
+      },
+      (false, err) => err,
+    };
+    (config, err)
+  }
+
+  /// The default config which never overwrites the file.
+  fn read_only() -> Self {
+    Self {
+      read_only: true,
+      ..Self::default()
+    }
+  }
+
+  /// Parse, migrate and validate the config.
+  /// Return the config and whether it's changed by migrations or validation.
+  fn parse(content: &str) -> Result<(Self, bool), String> {
+    let mut value: Map<String, Value> = serde_json::from_str(content).map_err(|e| e.to_string())?;
+
+    let version = value
+      .get("version")
+      .map(|v| v.as_u64().ok_or("version is not a number"))
+      .transpose()?
+      .unwrap_or(0) as usize;
+    if version > MIGRATIONS.len() {
+      return Err(format!(
+        "config version {} is newer than the supported version {}",
+        version,
+        Self::VERSION
+      ));
+    }
+    for migration in &MIGRATIONS[version..] {
+      migration(&mut value);
+    }
+    value.insert("version".into(), Self::VERSION.into());
+
+    let mut config: Self =
+      serde_json::from_value(Value::Object(value)).map_err(|e| e.to_string())?;
+    let clamped = config.validate();
+    Ok((config, version != MIGRATIONS.len() || clamped))
+  }
+
+  /// Clamp values into their valid ranges and make sure the active profile exists.
+  /// Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let mut changed = false;
+    if self.profiles.is_empty() {
+      warn!("No profile found, creating the default profile");
+      self
+        .profiles
+        .insert(Self::DEFAULT_PROFILE.into(), Profile::default());
+      changed = true;
+    }
+    if !self.profiles.contains_key(&self.active_profile) {
+      let first = self.profiles.keys().next().unwrap().clone();
+      warn!(
+        "Profile {:?} not found, using {:?}",
+        self.active_profile, first
+      );
+      self.active_profile = first;
+      changed = true;
+    }
+    for profile in self.profiles.values_mut() {
+      changed |= profile.validate();
+    }
+    changed
+  }
+
+  /// The active profile.
+  pub fn profile(&self) -> &Profile {
+    &self.profiles[&self.active_profile]
+  }
+
+  pub fn profile_mut(&mut self) -> &mut Profile {
+    self
+      .profiles
+      .get_mut(&self.active_profile)
+      .expect("active profile should exist")
+  }
+
+  /// Switch to another existing profile.
+  pub fn select_profile(&mut self, name: &str) -> Result<(), Error> {
+    if !self.profiles.contains_key(name) {
+      return Err(Error::Profile(format!("Profile {:?} not found", name)));
+    }
+    self.active_profile = name.into();
+    Ok(())
+  }
+
+  /// Trim the name and make sure it's not empty or taken.
+  fn check_new_profile_name(&self, name: &str) -> Result<String, Error> {
+    let name = name.trim();
+    if name.is_empty() {
+      return Err(Error::Profile("Profile name can't be empty".into()));
+    }
+    if self.profiles.contains_key(name) {
+      return Err(Error::Profile(format!("Profile {:?} already exists", name)));
+    }
+    Ok(name.into())
+  }
+
+  /// Create a profile with the default settings and switch to it.
+  pub fn create_profile(&mut self, name: &str) -> Result<(), Error> {
+    let name = self.check_new_profile_name(name)?;
+    self.profiles.insert(name.clone(), Profile::default());
+    self.active_profile = name;
+    Ok(())
+  }
+
+  /// Copy the active profile with a new name and switch to it.
+  pub fn duplicate_profile(&mut self, name: &str) -> Result<(), Error> {
+    let name = self.check_new_profile_name(name)?;
+    self.profiles.insert(name.clone(), self.profile().clone());
+    self.active_profile = name;
+    Ok(())
+  }
+
+  /// Rename the active profile.
+  pub fn rename_profile(&mut self, name: &str) -> Result<(), Error> {
+    let name = self.check_new_profile_name(name)?;
+    let profile = self.profiles.remove(&self.active_profile).unwrap();
+    self.profiles.insert(name.clone(), profile);
+    self.active_profile = name;
+    Ok(())
+  }
+
+  /// Delete the active profile and switch to the first one. The last profile can't be deleted.
+  pub fn delete_profile(&mut self) -> Result<(), Error> {
+    if self.profiles.len() == 1 {
+      return Err(Error::Profile("The last profile can't be deleted".into()));
+    }
+    self.profiles.remove(&self.active_profile);
+    self.active_profile = self.profiles.keys().next().unwrap().clone();
+    Ok(())
+  }
+
+  /// Save the config to its path atomically.
+  pub fn save(&self) -> Result<(), Error> {
+    if self.read_only {
+      return Err(Error::Config(format!(
+        "Not saving the config, {} is invalid or unreadable. Fix or remove it and restart StickDeck",
+        self.path.display()
+      )));
+    }
+    let err =
+      |e: std::io::Error| Error::Config(format!("Failed to save {}: {}", self.path.display(), e));
+
+    if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+      fs::create_dir_all(dir).map_err(err)?;
+    }
+    let content = serde_json::to_string_pretty(self).expect("config should be serializable");
+    // write to a temporary file then rename it, so the config is never half-written
+    let mut tmp = self.path.clone().into_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, content).map_err(err)?;
+    fs::rename(&tmp, &self.path).map_err(err)
+  }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+  let mut backup = path.as_os_str().to_owned();
+  backup.push(".bak");
+  PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::process;
+
+  fn temp_path(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("stickdeck-config-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir.join("stickdeck").join("config.json")
+  }
+
+  #[test]
+  fn test_parse_migrate_validate() {
+    // v0 with a zero interval
+    let (config, changed) =
+      Config::parse(r#"{ "dark": false, "input_update_interval_ms": 0 }"#).unwrap();
+    assert!(changed);
+    assert_eq!(config.version, Config::VERSION);
+    assert!(!config.dark);
+    assert_eq!(config.profile(), &Profile::default());
+
+    // v1 settings are moved into the default profile and clamped
+    let (config, changed) =
+      Config::parse(r#"{ "version": 1, "input_update_interval_ms": 1000 }"#).unwrap();
+    assert!(changed);
+    assert_eq!(config.active_profile, Config::DEFAULT_PROFILE);
+    assert_eq!(config.profile().input_update_interval_ms, 50);
+
+    // unchanged, missing fields use the default values
+    let (config, changed) = Config::parse(r#"{ "version": 2 }"#).unwrap();
+    assert!(!changed);
+    assert!(config.dark);
+    assert_eq!(config.bind, Bind::Auto);
+
+    let (config, _) =
+      Config::parse(r#"{ "version": 2, "bind": { "Address": "fe80::1" } }"#).unwrap();
+    assert_eq!(config.bind, Bind::Address("fe80::1".parse().unwrap()));
+
+    // the active profile must exist
+    let (config, changed) =
+      Config::parse(r#"{ "version": 2, "active_profile": "x", "profiles": { "a": {}, "b": {} } }"#)
+        .unwrap();
+    assert!(changed);
+    assert_eq!(config.active_profile, "a");
+
+    assert!(Config::parse(r#"{ "version": 999 }"#).is_err());
+    assert!(Config::parse(r#"{ "dark": 1 }"#).is_err());
+    assert!(Config::parse("not json").is_err());
+  }
+
+  #[test]
+  fn test_profiles() {
+    let mut config = Config::default();
+    config.profile_mut().input_update_interval_ms = 10;
+
+    config.duplicate_profile(" Game ").unwrap();
+    assert_eq!(config.active_profile, "Game");
+    assert_eq!(config.profile().input_update_interval_ms, 10);
+
+    config.create_profile("PC").unwrap();
+    assert_eq!(config.profile(), &Profile::default());
+    assert!(config.create_profile("Game").is_err());
+    assert!(config.create_profile("  ").is_err());
+
+    config.rename_profile("Laptop").unwrap();
+    assert_eq!(
+      config.profiles.keys().collect::<Vec<_>>(),
+      ["Default", "Game", "Laptop"]
+    );
+
+    config.select_profile("Game").unwrap();
+    assert!(config.select_profile("PC").is_err());
+    config.delete_profile().unwrap();
+    assert_eq!(config.active_profile, "Default");
+    config.delete_profile().unwrap();
+    assert!(config.delete_profile().is_err());
+    assert_eq!(config.active_profile, "Laptop");
+  }
+
+  #[test]
+  fn test_init_and_save() {
+    let path = temp_path("init");
+
+    // create a new config
+    let (mut config, err) = Config::init(path.clone());
+    assert!(err.is_none());
+    assert_eq!(
+      fs::read_to_string(&path).unwrap(),
+      serde_json::to_string_pretty(&config).unwrap()
+    );
+
+    config.dark = false;
+    config.save().unwrap();
+    let (loaded, err) = Config::init(path.clone());
+    assert!(err.is_none());
+    assert_eq!(loaded, config);
+
+    // invalid config is reported and backed up
+    fs::write(&path, "{ broken").unwrap();
+    let (config, err) = Config::init(path.clone());
+    assert!(matches!(err, Some(Error::Config(_))));
+    assert_eq!(
+      config,
+      Config {
+        path: path.clone(),
+        ..Default::default()
+      }
+    );
+    assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "{ broken");
+
+    // the invalid file is kept if it can't be backed up
+    fs::write(&path, "{ broken again").unwrap();
+    fs::remove_file(backup_path(&path)).unwrap();
+    fs::create_dir(backup_path(&path)).unwrap();
+    let (mut config, err) = Config::init(path.clone());
+    assert!(matches!(err, Some(Error::Config(_))));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{ broken again");
+    // and later changes aren't saved over it
+    config.dark = false;
+    assert!(config.save().is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{ broken again");
+
+    fs::remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
   }
 }
@@ -0,0 +1,246 @@
+use crate::server::ClientInfo;
+use iced::{
+  alignment::{Horizontal, Vertical},
+  mouse,
+  widget::canvas::{self, Frame, Geometry, Path, Stroke, Text},
+  Color, Point, Rectangle, Renderer, Theme,
+};
+use std::{
+  collections::{HashMap, VecDeque},
+  time::{Duration, Instant},
+};
+
+/// How often the rates are sampled.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of samples in the update rate graph.
+const HISTORY_LEN: usize = 60;
+
+/// Per-second rates of a client over the last sample interval.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rates {
+  pub packets: f64,
+  pub bytes: f64,
+  /// Mean time to write a packet to the socket.
+  pub write_time: Duration,
+}
+
+/// Turn the counters of the input loop and the clients into rates, sampled every [`SAMPLE_INTERVAL`].
+pub struct Dashboard {
+  last_sample: Instant,
+  last_updates: u64,
+  /// The counters of each client at the last sample.
+  last_clients: HashMap<u64, ClientInfo>,
+  rates: HashMap<u64, Rates>,
+  /// Input updates per second, the latest at the back.
+  history: VecDeque<f64>,
+}
+
+impl Dashboard {
+  pub fn new(now: Instant, updates: u64) -> Self {
+    Self {
+      last_sample: now,
+      last_updates: updates,
+      last_clients: HashMap::new(),
+      rates: HashMap::new(),
+      history: VecDeque::with_capacity(HISTORY_LEN),
+    }
+  }
+
+  /// Sample the counters if [`SAMPLE_INTERVAL`] has elapsed since the last sample.
+  /// `updates` is the total number of input updates.
+  pub fn sample(&mut self, now: Instant, updates: u64, clients: &[ClientInfo]) {
+    let elapsed = now.duration_since(self.last_sample);
+    if elapsed < SAMPLE_INTERVAL {
+      return;
+    }
+    let secs = elapsed.as_secs_f64();
+
+    if self.history.len() == HISTORY_LEN {
+      self.history.pop_front();
+    }
+    self
+      .history
+      .push_back(updates.saturating_sub(self.last_updates) as f64 / secs);
+
+    self.rates.clear();
+    // new clients are rated from the next sample
+    for (client, last) in clients
+      .iter()
+      .filter_map(|c| self.last_clients.get(&c.id).map(|last| (c, last)))
+    {
+      // saturate in case the counters are from another server
+      let packets = client.packets_sent.saturating_sub(last.packets_sent);
+      self.rates.insert(
+        client.id,
+        Rates {
+          packets: packets as f64 / secs,
+          bytes: client.bytes_sent.saturating_sub(last.bytes_sent) as f64 / secs,
+          write_time: match packets {
+            0 => Duration::ZERO,
+            n => client.write_time.saturating_sub(last.write_time) / n as u32,
+          },
+        },
+      );
+    }
+    self.last_clients = clients.iter().map(|c| (c.id, c.clone())).collect();
+
+    self.last_sample = now;
+    self.last_updates = updates;
+  }
+
+  /// Rates of the client, `None` until the client is sampled twice.
+  pub fn rates(&self, id: u64) -> Option<Rates> {
+    self.rates.get(&id).copied()
+  }
+
+  /// Draw the input updates per second with the expected rate.
+  pub fn graph(&self, expected: f64) -> UpdateRateGraph<'_> {
+    UpdateRateGraph {
+      history: &self.history,
+      expected,
+    }
+  }
+}
+
+/// Format a duration as `h:mm:ss`.
+pub fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Format bytes per second with a unit.
+pub fn format_bytes_rate(bytes: f64) -> String {
+  if bytes >= 1024.0 * 1024.0 {
+    format!("{:.1} MB/s", bytes / 1024.0 / 1024.0)
+  } else if bytes >= 1024.0 {
+    format!("{:.1} KB/s", bytes / 1024.0)
+  } else {
+    format!("{:.0} B/s", bytes)
+  }
+}
+
+/// A line graph of the input update rate, see [`Dashboard::graph`].
+pub struct UpdateRateGraph<'a> {
+  history: &'a VecDeque<f64>,
+  expected: f64,
+}
+
+impl<Message> canvas::Program<Message> for UpdateRateGraph<'_> {
+  type State = ();
+
+  fn draw(
+    &self,
+    _state: &Self::State,
+    renderer: &Renderer,
+    theme: &Theme,
+    bounds: Rectangle,
+    _cursor: mouse::Cursor,
+  ) -> Vec<Geometry> {
+    let mut frame = Frame::new(renderer, bounds.size());
+    let palette = theme.palette();
+
+    // leave some room above the expected rate
+    let max = self.history.iter().fold(self.expected, |a, &b| a.max(b)) * 1.2;
+    let y = |rate: f64| (bounds.height as f64 * (1.0 - rate / max)) as f32;
+    let step = bounds.width / (HISTORY_LEN - 1) as f32;
+    // the latest sample is at the right
+    let x = |i: usize| bounds.width - (self.history.len() - 1 - i) as f32 * step;
+
+    frame.stroke(
+      &Path::line(
+        Point::new(0.0, y(self.expected)),
+        Point::new(bounds.width, y(self.expected)),
+      ),
+      Stroke::default().with_color(Color {
+        a: 0.4,
+        ..palette.text
+      }),
+    );
+    frame.stroke(
+      &Path::new(|builder| {
+        for (i, &rate) in self.history.iter().enumerate() {
+          let point = Point::new(x(i), y(rate));
+          match i {
+            0 => builder.move_to(point),
+            _ => builder.line_to(point),
+          }
+        }
+      }),
+      Stroke::default()
+        .with_color(palette.primary)
+        .with_width(2.0),
+    );
+    frame.stroke(
+      &Path::rectangle(Point::ORIGIN, bounds.size()),
+      Stroke::default().with_color(palette.text),
+    );
+    frame.fill_text(Text {
+      content: format!(
+        "{:.0}/s (expected {:.0}/s)",
+        self.history.back().copied().unwrap_or_default(),
+        self.expected
+      ),
+      position: Point::new(8.0, 8.0),
+      color: palette.text,
+      size: 16.0,
+      horizontal_alignment: Horizontal::Left,
+      vertical_alignment: Vertical::Top,
+      ..Text::default()
+    });
+
+    vec![frame.into_geometry()]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use stickdeck_common::Role;
+
+  fn client(id: u64, packets: u64) -> ClientInfo {
+    ClientInfo {
+      id,
+      addr: "127.0.0.1:7777".parse().unwrap(),
+      role: Role::Controller,
+      connected_at: Instant::now(),
+      packets_sent: packets,
+      bytes_sent: packets * 16,
+      write_time: Duration::from_micros(packets * 10),
+      queued: 0,
+    }
+  }
+
+  #[test]
+  fn test_sample() {
+    let start = Instant::now();
+    let mut dashboard = Dashboard::new(start, 0);
+
+    // not sampled before the interval
+    dashboard.sample(start + Duration::from_millis(500), 100, &[]);
+    assert!(dashboard.history.is_empty());
+
+    // a new client is rated from the next sample
+    dashboard.sample(start + Duration::from_secs(2), 600, &[client(1, 50)]);
+    assert_eq!(dashboard.history, [300.0]);
+    assert_eq!(dashboard.rates(1), None);
+
+    dashboard.sample(start + Duration::from_secs(3), 900, &[client(1, 350)]);
+    assert_eq!(dashboard.history, [300.0, 300.0]);
+    let rates = dashboard.rates(1).unwrap();
+    assert_eq!(rates.packets, 300.0);
+    assert_eq!(rates.bytes, 4800.0);
+    assert_eq!(rates.write_time, Duration::from_micros(10));
+
+    // disconnected clients are removed
+    dashboard.sample(start + Duration::from_secs(4), 900, &[]);
+    assert_eq!(dashboard.history, [300.0, 300.0, 0.0]);
+    assert_eq!(dashboard.rates(1), None);
+  }
+
+  #[test]
+  fn test_format() {
+    assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+    assert_eq!(format_bytes_rate(512.0), "512 B/s");
+    assert_eq!(format_bytes_rate(5324.8), "5.2 KB/s");
+  }
+}
@@ -1,16 +1,33 @@
 use std::fmt;
+use steamworks::SteamError;
 
 /// Error type for the deck module.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
   /// Invalid handle (handle value is 0).
   InvalidHandle,
+  /// Failed to initialize the Steam API.
+  SteamInit(SteamError),
+  /// Steam is running the app with a different App ID than the configured one.
+  AppIdMismatch { expected: u32, actual: u32 },
+  /// Failed to load or save the config.
+  Config(String),
+  /// Invalid profile operation.
+  Profile(String),
 }
 
 impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
       Error::InvalidHandle => write!(f, "Invalid handle: handle value is 0"),
+      Error::SteamInit(e) => write!(f, "Failed to initialize Steam: {}", e),
+      Error::AppIdMismatch { expected, actual } => write!(
+        f,
+        "App ID mismatch: configured {} but Steam is running the app as {}, \
+        check steam_appid.txt and how the app is launched",
+        expected, actual
+      ),
+      Error::Config(message) | Error::Profile(message) => write!(f, "{}", message),
     }
   }
 }
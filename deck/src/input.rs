@@ -1,29 +1,103 @@
 mod action;
+mod controllers;
+mod digital;
+mod filter;
+mod flick;
+mod gyro;
+mod motion;
+mod scheduler;
+mod sensitivity;
 mod xbox;
 
-use crate::gamepad::{XButtons, XGamepad};
+use crate::{
+  error::Error,
+  gamepad::{XButtons, XGamepad},
+  profile::Profile,
+};
 use action::{InputAction, InputActionData, InputDigitalAction, UpdatableInputAction};
-use log::{info, trace};
+use controllers::Controllers;
+pub use controllers::Selection;
+use filter::Pointer;
+use log::{info, trace, warn};
+use motion::PadMotion;
+pub use scheduler::Jitter;
+use scheduler::Scheduler;
 use std::{
+  mem,
   sync::mpsc,
   thread,
   time::{Duration, Instant},
 };
-use steamworks::{Client, ClientManager, Input, SResult, SingleClient};
-use steamworks_sys::InputHandle_t;
-use stickdeck_common::{perf, Mouse, MouseButton, Packet};
+use steamworks::{Client, ClientManager, Input, SingleClient};
+use steamworks_sys::{InputHandle_t, STEAM_INPUT_MAX_COUNT};
+use stickdeck_common::{counter, perf, Keys, Mouse, MouseButton, Packet};
 use tokio::sync::watch;
 use xbox::XBoxControls;
 
 pub struct InputConfig {
-  pub interval_ms: u64,
-  pub ui_tx: watch::Sender<String>,
+  pub profile: Profile,
+  /// Runtime updates, applied without reconnecting the client.
+  pub control_rx: mpsc::Receiver<Control>,
+  /// Acknowledgements and status changes sent back to the UI.
+  pub event_tx: mpsc::Sender<Event>,
+  pub ui_tx: watch::Sender<Snapshot>,
   pub connected_rx: mpsc::Receiver<mpsc::SyncSender<Packet<XGamepad>>>,
   pub ui_update_interval_ms: u128,
 }
 
-pub fn spawn(input_rx: mpsc::Receiver<InputConfig>) -> SResult<()> {
-  let (client, single) = Client::init()?;
+/// Runtime updates for the input thread.
+#[derive(Debug)]
+pub enum Control {
+  /// Apply new settings (interval, processing and mappings).
+  /// The sequence number is sent back in [`Event::Applied`].
+  Apply { seq: u64, profile: Profile },
+  /// Select the controller of the first pad.
+  SelectController(Selection),
+  /// Turn a full circle with the flick stick of the first pad, to check `pixels_per_360`.
+  CalibrateFlickStick,
+  /// Center the gyro steering of all pads.
+  RecenterSteering,
+}
+
+/// Events from the input thread.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+  /// The [`Control`] with the sequence number is applied.
+  Applied(u64),
+  /// Controllers are connected or disconnected, or pad indexes are changed.
+  ControllersChanged {
+    connected: Vec<InputHandle_t>,
+    /// Handles indexed by the pad index. `None` for free pad indexes.
+    pads: Vec<Option<InputHandle_t>>,
+  },
+}
+
+/// The input state shown in the UI.
+/// It's fixed-size, so sending it every UI update doesn't allocate.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Snapshot {
+  /// The state of the first connected pad, `None` if no controller is connected.
+  pub gamepad: Option<XGamepad>,
+  /// Number of connected pads.
+  pub pads: usize,
+  /// Mouse movement accumulated since the last snapshot.
+  pub mouse_dx: i32,
+  pub mouse_dy: i32,
+  pub mouse_buttons: MouseButton,
+  pub jitter: Jitter,
+}
+
+pub fn spawn(app_id: u32, config: InputConfig) -> Result<(), Error> {
+  let (client, single) = Client::init_app(app_id).map_err(Error::SteamInit)?;
+
+  let actual = client.utils().app_id().0;
+  if actual != app_id {
+    return Err(Error::AppIdMismatch {
+      expected: app_id,
+      actual,
+    });
+  }
+  info!("Steam initialized with App ID {}", app_id);
 
   // steam client is not `Send`, so we have to use std thread and channel instead of tokio
   thread::spawn(move || {
@@ -33,139 +107,185 @@ pub fn spawn(input_rx: mpsc::Receiver<InputConfig>) -> SResult<()> {
     // try to init controls from vdf
     let xbox = poll(&single, 100, retry(10, || XBoxControls::new(&input).ok()));
     info!("XBox controls initialized");
-
-    // try to get input handles (input devices)
-    let input_handles = poll(
-      &single,
-      100,
-      retry(10, || {
-        let handles = input.get_connected_controllers();
-        if !handles.is_empty() {
-          info!("num of input handles: {:?}", handles.len());
-          Some(handles)
-        } else {
-          info!("no input handles, retrying...");
-          None
-        }
-      }),
-    );
-
-    // enable xbox control action set for the first input handle
-    input.activate_action_set_handle(input_handles[0], xbox.handle);
+    if xbox.gyro_move.is_none() || xbox.btn_precision.is_none() || xbox.btn_recenter.is_none() {
+      warn!("Some actions are not found, run `stickdeck setup` to update the action manifest");
+    }
 
     let InputConfig {
-      interval_ms,
+      mut profile,
+      control_rx,
+      event_tx,
       ui_tx,
       connected_rx,
       ui_update_interval_ms,
-    } = input_rx.recv().expect("Failed to receive input data");
+    } = config;
     let mut net_tx = None;
-    let mut last_gamepad = XGamepad::default();
+    // the last sent state of each pad, `None` if the pad is not sent or removed
+    let mut last_gamepads: Vec<Option<XGamepad>> = vec![None; STEAM_INPUT_MAX_COUNT as usize];
     let mut last_mouse_button = MouseButton::default();
+    let mut last_keys = Keys::default();
+    // mouse movement of all pads which doesn't fit in the last packets
+    let mut mouse_overflow = [0; 2];
     let mut last_update = Instant::now();
-    let mut ui_str_buffer = String::new(); // prevent reallocation
-
-    poll(
-      &single,
-      interval_ms,
-      forever(|| {
-        // check if the client is connected
-        if net_tx.is_none() {
-          net_tx = connected_rx.try_recv().ok();
+    let mut snapshot = Snapshot::default();
+    let mut controllers = Controllers::new();
+    let mut scheduler = Scheduler::new(interval(&profile));
+    let mut motions: Vec<PadMotion> = (0..STEAM_INPUT_MAX_COUNT)
+      .map(|_| PadMotion::new(&profile))
+      .collect();
+    // the controller of each motion state, which is reset when another controller takes the pad
+    let mut motion_handles: Vec<Option<InputHandle_t>> = vec![None; STEAM_INPUT_MAX_COUNT as usize];
+    let mut last_read = Instant::now();
+
+    loop {
+      // apply runtime updates
+      for control in control_rx.try_iter() {
+        match control {
+          Control::Apply { seq, profile: p } => {
+            info!("Profile updated: {:?}", p);
+            profile = p;
+            scheduler.set_interval(interval(&profile));
+            motions.iter_mut().for_each(|m| m.set_profile(&profile));
+            // the UI may be gone when exiting
+            event_tx.send(Event::Applied(seq)).ok();
+          }
+          Control::SelectController(selection) => {
+            info!("Controller selected: {}", selection);
+            if controllers.select(selection) {
+              controllers_changed(&controllers, &event_tx);
+            }
+          }
+          Control::CalibrateFlickStick => {
+            if let Some(i) = controllers.pads().iter().position(Option::is_some) {
+              motions[i].calibrate();
+            }
+          }
+          Control::RecenterSteering => motions.iter_mut().for_each(PadMotion::recenter),
+        }
+      }
+
+      // handles may change after sleep/resume or when a controller is plugged in
+      if controllers.update(&input, xbox.handle) {
+        controllers_changed(&controllers, &event_tx);
+      }
+
+      // check if a (new) server is started
+      if let Some(tx) = connected_rx.try_iter().last() {
+        net_tx = Some(tx);
+        // send the full state to the new server
+        last_gamepads.fill(None);
+        last_mouse_button = MouseButton::default();
+        last_keys = Keys::default();
+        mouse_overflow = [0; 2];
+      }
+
+      let update_ui = last_update.elapsed().as_millis() > ui_update_interval_ms;
+
+      // only send data if client is connected
+      let mut connected = true;
+      let mut send_packet = |p: Packet<XGamepad>| {
+        if let Some(tx) = &net_tx {
+          // the receiver is dropped when the client is disconnected or the server is stopped
+          trace!("Send {:?}", p);
+          connected &= perf!("net_tx.send", tx.send(p).is_ok(), 10);
         }
+      };
+
+      let now = Instant::now();
+      let dt = (now - last_read).as_secs_f32();
+      last_read = now;
 
-        // prepare ctx
-        let mut ui_str = if last_update.elapsed().as_millis() > ui_update_interval_ms {
-          last_update = Instant::now();
-          ui_str_buffer.clear();
-          Some(&mut ui_str_buffer) // re-use the buffer
-        } else {
-          None
-        };
-        let mut ctx = (&input, input_handles[0], &mut ui_str);
-
-        let mut gamepad = XGamepad::default();
-        let mut mouse = Mouse::default();
-
-        // digital buttons
-        let raw = &mut gamepad.buttons.raw;
-        let mb = &mut mouse.buttons;
-        update_btn(&xbox.btn_up, &mut ctx, || *raw |= XButtons::UP);
-        update_btn(&xbox.btn_down, &mut ctx, || *raw |= XButtons::DOWN);
-        update_btn(&xbox.btn_left, &mut ctx, || *raw |= XButtons::LEFT);
-        update_btn(&xbox.btn_right, &mut ctx, || *raw |= XButtons::RIGHT);
-        update_btn(&xbox.btn_start, &mut ctx, || *raw |= XButtons::START);
-        update_btn(&xbox.btn_back, &mut ctx, || *raw |= XButtons::BACK);
-        update_btn(&xbox.btn_l_thumb, &mut ctx, || *raw |= XButtons::LTHUMB);
-        update_btn(&xbox.btn_r_thumb, &mut ctx, || *raw |= XButtons::RTHUMB);
-        update_btn(&xbox.btn_lb, &mut ctx, || *raw |= XButtons::LB);
-        update_btn(&xbox.btn_rb, &mut ctx, || *raw |= XButtons::RB);
-        update_btn(&xbox.btn_a, &mut ctx, || *raw |= XButtons::A);
-        update_btn(&xbox.btn_b, &mut ctx, || *raw |= XButtons::B);
-        update_btn(&xbox.btn_x, &mut ctx, || *raw |= XButtons::X);
-        update_btn(&xbox.btn_y, &mut ctx, || *raw |= XButtons::Y);
-        update_btn(&xbox.btn_l_mouse, &mut ctx, || mb.mark_left_button_down());
-        update_btn(&xbox.btn_r_mouse, &mut ctx, || mb.mark_right_button_down());
-
-        // analog actions
-        update_input(&xbox.lt, &mut ctx, |data| {
-          gamepad.left_trigger = scale_f32_to_u8(data.x)
-        });
-        update_input(&xbox.rt, &mut ctx, |data| {
-          gamepad.right_trigger = scale_f32_to_u8(data.x)
-        });
-        update_input(&xbox.l_move, &mut ctx, |data| {
-          gamepad.thumb_lx = scale_f32_to_i16(data.x);
-          gamepad.thumb_ly = scale_f32_to_i16(data.y);
-        });
-        update_input(&xbox.r_move, &mut ctx, |data| {
-          gamepad.thumb_rx = scale_f32_to_i16(data.x);
-          gamepad.thumb_ry = scale_f32_to_i16(data.y);
-        });
-        update_input(&xbox.mouse_move, &mut ctx, |data| {
-          mouse.x = crop_f32_to_i8(data.x);
-          mouse.y = crop_f32_to_i8(data.y);
-        });
-        update_input(&xbox.mouse_scroll, &mut ctx, |data| {
-          mouse.scroll = crop_f32_to_i8(data.y);
-        });
-
-        // only send data if client is connected
-        net_tx.as_ref().inspect(|tx| {
-          let send_packet = |p: Packet<XGamepad>| {
-            trace!("Send {:?}", p);
-            perf!("net_tx.send", tx.send(p).expect("Failed to send data"), 10);
-          };
-
-          // gamepad changed
-          if gamepad != last_gamepad {
-            send_packet(Packet::Gamepad(gamepad.clone()));
-            last_gamepad = gamepad;
+      // trackpads of all controllers move the same mouse, and sticks press the same keys
+      let mut mouse = Mouse::default();
+      let mut movement = [0; 2];
+      let mut keys = Keys::default();
+      let pads = last_gamepads
+        .iter_mut()
+        .zip(&mut motions)
+        .zip(&mut motion_handles);
+      for (i, ((last, motion), motion_handle)) in pads.enumerate() {
+        let pad = i as u8;
+        let handle = controllers.pads().get(i).copied().flatten();
+        // don't carry the smoothing, flick or steering state over to another controller
+        if handle.is_some() && *motion_handle != handle {
+          *motion = PadMotion::new(&profile);
+        }
+        *motion_handle = handle;
+        match handle {
+          Some(handle) => {
+            let (mut gamepad, m, pointer) = read(&xbox, &(&input, handle));
+            keys.0 |= motion.sticks(&mut gamepad).0;
+            let [x, y] = motion.apply(&pointer, dt, &mut gamepad);
+            movement[0] += x;
+            movement[1] += y;
+            merge_mouse(&mut mouse, &mut movement, &m);
+            if update_ui && snapshot.gamepad.is_none() {
+              snapshot.gamepad = Some(gamepad.clone());
+            }
+
+            // gamepad changed, or send every tick in fixed-rate mode
+            if profile.fixed_rate || last.as_ref() != Some(&gamepad) {
+              send_packet(Packet::Gamepad(pad, gamepad.clone()));
+              *last = Some(gamepad);
+            }
           }
-          // mouse moved or scrolled or button state changed
-          // DON'T just check if current mouse equals last mouse
-          // because even if the x/y/scroll is the same with the last,
-          // we should still send the data as the delta if they are not 0
-          if mouse.x != 0 || mouse.y != 0 || mouse.buttons != last_mouse_button || mouse.scroll != 0
-          {
-            send_packet(Packet::Mouse(mouse));
-            last_mouse_button = mouse.buttons;
+          None => {
+            // the client should unplug the virtual gamepad
+            if last.take().is_some() {
+              send_packet(Packet::PadRemoved(pad));
+            }
           }
-        });
-        if let Some(s) = ui_str {
-          perf!(
-            "ui_tx.send",
-            ui_tx.send(s.clone()).expect("Failed to send UI data"),
-            10
-          )
         }
-      }),
-    );
+      }
+
+      [mouse.x, mouse.y] = fit_i8(movement, &mut mouse_overflow);
+
+      // mouse moved or scrolled or button state changed
+      // DON'T just check if current mouse equals last mouse
+      // because even if the x/y/scroll is the same with the last,
+      // we should still send the data as the delta if they are not 0
+      if mouse.x != 0 || mouse.y != 0 || mouse.buttons != last_mouse_button || mouse.scroll != 0 {
+        send_packet(Packet::Mouse(mouse));
+        last_mouse_button = mouse.buttons;
+      }
+      if keys != last_keys {
+        send_packet(Packet::Keys(keys));
+        last_keys = keys;
+      }
+      snapshot.mouse_dx += mouse.x as i32;
+      snapshot.mouse_dy += mouse.y as i32;
+
+      if !connected {
+        net_tx = None;
+      }
+      if update_ui {
+        last_update = Instant::now();
+        snapshot.pads = controllers.pads().iter().flatten().count();
+        snapshot.mouse_buttons = mouse.buttons;
+        snapshot.jitter = scheduler.jitter().clone();
+        // reset the accumulated mouse movement for the next snapshot
+        perf!(
+          "ui_tx.send",
+          ui_tx
+            .send(mem::take(&mut snapshot))
+            .expect("Failed to send UI data"),
+          10
+        )
+      }
+
+      perf!("poll", single.run_callbacks(), 10);
+      counter!("input updates").add(1);
+      scheduler.wait();
+    }
   });
 
   Ok(())
 }
 
+fn interval(profile: &Profile) -> Duration {
+  Duration::from_millis(profile.input_update_interval_ms)
+}
+
 /// Run a function until it returns a value.
 /// If the function returns [`None`], wait for the specified interval and run the Steam callbacks.
 fn poll<R>(single: &SingleClient, interval_ms: u64, mut f: impl FnMut() -> Option<R>) -> R {
@@ -191,46 +311,120 @@ fn retry<R>(mut n: usize, mut f: impl FnMut() -> Option<R>) -> impl FnMut() -> O
   }
 }
 
-/// Wrap a function to run forever when [`poll`]ed.
-fn forever(mut f: impl FnMut()) -> impl FnMut() -> Option<()> {
-  move || {
-    f();
-    None
+fn controllers_changed(controllers: &Controllers, event_tx: &mpsc::Sender<Event>) {
+  event_tx
+    .send(Event::ControllersChanged {
+      connected: controllers.connected().to_vec(),
+      pads: controllers.pads().to_vec(),
+    })
+    .ok();
+}
+
+/// Merge the buttons and scroll into the mouse, and sum the movement separately
+/// so it's not cropped to the range of a packet.
+fn merge_mouse(mouse: &mut Mouse, movement: &mut [i32; 2], other: &Mouse) {
+  movement[0] += other.x as i32;
+  movement[1] += other.y as i32;
+  mouse.buttons.0 |= other.buttons.0;
+  mouse.scroll = mouse.scroll.saturating_add(other.scroll);
+}
+
+/// Fit the movement and the `overflow` of earlier updates into a mouse packet.
+/// What doesn't fit is kept in `overflow` for the next packets.
+fn fit_i8(movement: [i32; 2], overflow: &mut [i32; 2]) -> [i8; 2] {
+  let mut sent = [0; 2];
+  for ((s, o), m) in sent.iter_mut().zip(overflow).zip(movement) {
+    let total = m.saturating_add(*o);
+    *s = total.clamp(i8::MIN as i32, i8::MAX as i32) as i8;
+    *o = total - *s as i32;
   }
+  sent
+}
+
+/// Read all actions of a controller.
+/// The mouse movement is returned as [`Pointer`] to be filtered.
+fn read(
+  xbox: &XBoxControls,
+  ctx: &(&Input<ClientManager>, InputHandle_t),
+) -> (XGamepad, Mouse, Pointer) {
+  let mut gamepad = XGamepad::default();
+  let mut mouse = Mouse::default();
+  let mut pointer = Pointer::default();
+
+  // digital buttons
+  let raw = &mut gamepad.buttons.raw;
+  let mb = &mut mouse.buttons;
+  update_btn(&xbox.btn_up, ctx, || *raw |= XButtons::UP);
+  update_btn(&xbox.btn_down, ctx, || *raw |= XButtons::DOWN);
+  update_btn(&xbox.btn_left, ctx, || *raw |= XButtons::LEFT);
+  update_btn(&xbox.btn_right, ctx, || *raw |= XButtons::RIGHT);
+  update_btn(&xbox.btn_start, ctx, || *raw |= XButtons::START);
+  update_btn(&xbox.btn_back, ctx, || *raw |= XButtons::BACK);
+  update_btn(&xbox.btn_l_thumb, ctx, || *raw |= XButtons::LTHUMB);
+  update_btn(&xbox.btn_r_thumb, ctx, || *raw |= XButtons::RTHUMB);
+  update_btn(&xbox.btn_lb, ctx, || *raw |= XButtons::LB);
+  update_btn(&xbox.btn_rb, ctx, || *raw |= XButtons::RB);
+  update_btn(&xbox.btn_a, ctx, || *raw |= XButtons::A);
+  update_btn(&xbox.btn_b, ctx, || *raw |= XButtons::B);
+  update_btn(&xbox.btn_x, ctx, || *raw |= XButtons::X);
+  update_btn(&xbox.btn_y, ctx, || *raw |= XButtons::Y);
+  update_btn(&xbox.btn_l_mouse, ctx, || mb.mark_left_button_down());
+  update_btn(&xbox.btn_r_mouse, ctx, || mb.mark_right_button_down());
+  if let Some(btn_precision) = &xbox.btn_precision {
+    update_btn(btn_precision, ctx, || pointer.precision = true);
+  }
+  if let Some(btn_recenter) = &xbox.btn_recenter {
+    update_btn(btn_recenter, ctx, || pointer.recenter = true);
+  }
+
+  // analog actions
+  update_input(&xbox.lt, ctx, |data| {
+    gamepad.left_trigger = scale_f32_to_u8(data.x)
+  });
+  update_input(&xbox.rt, ctx, |data| {
+    gamepad.right_trigger = scale_f32_to_u8(data.x)
+  });
+  update_input(&xbox.l_move, ctx, |data| {
+    gamepad.thumb_lx = scale_f32_to_i16(data.x);
+    gamepad.thumb_ly = scale_f32_to_i16(data.y);
+  });
+  update_input(&xbox.r_move, ctx, |data| {
+    gamepad.thumb_rx = scale_f32_to_i16(data.x);
+    gamepad.thumb_ry = scale_f32_to_i16(data.y);
+  });
+  update_input(&xbox.mouse_move, ctx, |data| {
+    pointer.trackpad = [data.x, data.y];
+  });
+  if let Some(gyro_move) = &xbox.gyro_move {
+    update_input(gyro_move, ctx, |data| pointer.gyro = [data.x, data.y]);
+  }
+  update_input(&xbox.mouse_scroll, ctx, |data| {
+    mouse.scroll = crop_f32_to_i8(data.y);
+  });
+
+  // motion data for gyro steering
+  let motion = ctx.0.get_motion_data(ctx.1);
+  pointer.rot_vel = [motion.rotVelX, motion.rotVelY, motion.rotVelZ];
+
+  (gamepad, mouse, pointer)
 }
 
 fn update_input<Data: InputActionData>(
   action: &InputAction<Data>,
-  (input, input_handle, ui_str): &mut (
-    &Input<ClientManager>,
-    InputHandle_t,
-    &mut Option<&mut String>,
-  ),
+  (input, input_handle): &(&Input<ClientManager>, InputHandle_t),
   mut cb: impl FnMut(&Data),
 ) where
   InputAction<Data>: UpdatableInputAction<Data>,
 {
   let data = action.update(input, *input_handle);
-
   if data.is_active() {
-    if let Some(s) = ui_str.as_mut() {
-      s.push_str(&format!("{}: {}\n", action.name, data.to_string()))
-    }
     cb(&data);
-  } else {
-    if let Some(s) = ui_str.as_mut() {
-      s.push_str(&format!("{}: inactive\n", action.name))
-    }
   }
 }
 
 fn update_btn(
   action: &InputDigitalAction,
-  ctx: &mut (
-    &Input<ClientManager>,
-    InputHandle_t,
-    &mut Option<&mut String>,
-  ),
+  ctx: &(&Input<ClientManager>, InputHandle_t),
   mut cb: impl FnMut(),
 ) {
   update_input(action, ctx, |data| {
@@ -254,3 +448,19 @@ fn scale_f32_to_u8(f: f32) -> u8 {
 fn scale_f32_to_i16(f: f32) -> i16 {
   (f * i16::MAX as f32) as i16
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fit_i8() {
+    let mut overflow = [0; 2];
+    assert_eq!(fit_i8([200, -300], &mut overflow), [127, -128]);
+    assert_eq!(overflow, [73, -172]);
+    // the overflow is sent first
+    assert_eq!(fit_i8([10, 0], &mut overflow), [83, -128]);
+    assert_eq!(fit_i8([0, 0], &mut overflow), [0, -44]);
+    assert_eq!(overflow, [0, 0]);
+  }
+}
@@ -5,7 +5,6 @@ use steamworks::{ClientManager, Input};
 use steamworks_sys::{uint64, InputAnalogActionData_t, InputDigitalActionData_t, InputHandle_t};
 
 pub struct InputAction<Data> {
-  pub name: &'static str,
   pub handle: uint64,
   _phantom: PhantomData<Data>,
 }
@@ -18,7 +17,6 @@ impl InputAnalogAction {
   /// Return [`Err`] if the handle is invalid.
   pub fn new(input: &Input<ClientManager>, name: &'static str) -> Result<Self, Error> {
     Ok(Self {
-      name,
       handle: check_handle(input.get_analog_action_handle(name))?,
       _phantom: PhantomData,
     })
@@ -30,7 +28,6 @@ impl InputDigitalAction {
   /// Return [`Err`] if the handle is invalid.
   pub fn new(input: &Input<ClientManager>, name: &'static str) -> Result<Self, Error> {
     Ok(Self {
-      name,
       handle: check_handle(input.get_digital_action_handle(name))?,
       _phantom: PhantomData,
     })
@@ -64,29 +61,16 @@ impl UpdatableInputAction<InputDigitalActionData_t> for InputDigitalAction {
 
 pub trait InputActionData {
   fn is_active(&self) -> bool;
-  fn to_string(&self) -> String;
 }
 
 impl InputActionData for InputAnalogActionData_t {
   fn is_active(&self) -> bool {
     self.bActive
   }
-  fn to_string(&self) -> String {
-    // since InputAnalogActionData_t is #[repr(packed)]
-    // we can't reference its fields directly
-    // so we have to copy them to local variables
-    let e_mode = self.eMode;
-    let x = self.x;
-    let y = self.y;
-    format!("Analog {{ mode: {:?}, x: {}, y: {} }}", e_mode, x, y)
-  }
 }
 
 impl InputActionData for InputDigitalActionData_t {
   fn is_active(&self) -> bool {
     self.bActive
   }
-  fn to_string(&self) -> String {
-    format!("Digital {{ state: {} }}", self.bState)
-  }
 }
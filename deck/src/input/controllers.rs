@@ -0,0 +1,186 @@
+use log::info;
+use serde::Deserialize;
+use std::fmt;
+use steamworks::{ClientManager, Input};
+use steamworks_sys::{InputActionSetHandle_t, InputHandle_t, STEAM_INPUT_MAX_COUNT};
+
+/// Which controller is the first pad (player 1).
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+  /// Controllers keep the pad index assigned when they are connected.
+  #[default]
+  Auto,
+  /// A specific controller is moved to the first pad while it's connected.
+  Handle(InputHandle_t),
+}
+
+impl fmt::Display for Selection {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Selection::Auto => write!(f, "Auto"),
+      Selection::Handle(handle) => write!(f, "Controller {}", handle),
+    }
+  }
+}
+
+/// Track connected controllers (input handles), so controllers can be hot-plugged.
+/// Each controller is assigned a pad index which doesn't change while it's connected.
+pub struct Controllers {
+  /// Connected handles in the order reported by Steam.
+  connected: Vec<InputHandle_t>,
+  /// Handles indexed by the pad index. `None` for free pad indexes.
+  pads: Vec<Option<InputHandle_t>>,
+  selection: Selection,
+  /// Buffer to query handles without allocating.
+  buf: [InputHandle_t; STEAM_INPUT_MAX_COUNT as usize],
+}
+
+impl Controllers {
+  pub fn new() -> Self {
+    Self {
+      connected: Vec::new(),
+      pads: Vec::new(),
+      selection: Selection::Auto,
+      buf: [0; STEAM_INPUT_MAX_COUNT as usize],
+    }
+  }
+
+  pub fn connected(&self) -> &[InputHandle_t] {
+    &self.connected
+  }
+
+  /// Handles indexed by the pad index.
+  pub fn pads(&self) -> &[Option<InputHandle_t>] {
+    &self.pads
+  }
+
+  /// Select the controller of the first pad.
+  /// Return `true` if any pad is changed.
+  pub fn select(&mut self, selection: Selection) -> bool {
+    self.selection = selection;
+    self.assign()
+  }
+
+  /// Query the connected controllers from Steam and activate the action set on new controllers.
+  /// Return `true` if the connected controllers are changed.
+  pub fn update(
+    &mut self,
+    input: &Input<ClientManager>,
+    action_set: InputActionSetHandle_t,
+  ) -> bool {
+    let count = input.get_connected_controllers_slice(&mut self.buf);
+    if self.buf[..count] == self.connected[..] {
+      return false;
+    }
+
+    let handles = self.buf;
+    for &handle in self.set_connected(&handles[..count]).iter() {
+      info!("Controller connected: {}", handle);
+      input.activate_action_set_handle(handle, action_set);
+    }
+    true
+  }
+
+  /// Replace the connected controllers and return the new ones.
+  fn set_connected(&mut self, handles: &[InputHandle_t]) -> Vec<InputHandle_t> {
+    let new = handles
+      .iter()
+      .filter(|h| !self.connected.contains(h))
+      .copied()
+      .collect();
+    for handle in self.connected.iter().filter(|h| !handles.contains(h)) {
+      info!("Controller disconnected: {}", handle);
+    }
+    self.connected.clear();
+    self.connected.extend_from_slice(handles);
+    self.assign();
+    new
+  }
+
+  /// Assign pad indexes to connected controllers. Return `true` if any pad is changed.
+  fn assign(&mut self) -> bool {
+    let before = self.pads.clone();
+
+    // free pads of disconnected controllers
+    for pad in self.pads.iter_mut() {
+      if pad.is_some_and(|h| !self.connected.contains(&h)) {
+        *pad = None;
+      }
+    }
+
+    // move the selected controller to the first pad
+    if let Selection::Handle(handle) = self.selection {
+      if self.connected.contains(&handle) && self.pads.first() != Some(&Some(handle)) {
+        if self.pads.is_empty() {
+          self.pads.push(None);
+        }
+        // swap with the current first pad, or the current first pad takes a new pad index below
+        match self.pads.iter().position(|&p| p == Some(handle)) {
+          Some(i) => self.pads.swap(0, i),
+          None => self.pads[0] = Some(handle),
+        }
+      }
+    }
+
+    // new controllers take the first free pad index
+    for &handle in self.connected.iter() {
+      if !self.pads.contains(&Some(handle)) {
+        match self.pads.iter_mut().find(|p| p.is_none()) {
+          Some(pad) => *pad = Some(handle),
+          None => self.pads.push(Some(handle)),
+        }
+      }
+    }
+
+    while self.pads.last() == Some(&None) {
+      self.pads.pop();
+    }
+
+    before != self.pads
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_hotplug_and_selection() {
+    let mut controllers = Controllers::new();
+    assert!(controllers.pads().is_empty());
+
+    assert_eq!(controllers.set_connected(&[1, 2]), [1, 2]);
+    assert_eq!(controllers.pads(), [Some(1), Some(2)]);
+
+    assert!(controllers.select(Selection::Handle(2)));
+    assert_eq!(controllers.pads(), [Some(2), Some(1)]);
+    assert!(!controllers.select(Selection::Handle(2)));
+
+    // the selected controller is reconnected with a new handle after resume,
+    // other controllers keep their pad index
+    assert_eq!(controllers.set_connected(&[1, 3]), [3]);
+    assert_eq!(controllers.pads(), [Some(3), Some(1)]);
+    // and back again
+    assert_eq!(controllers.set_connected(&[1, 2]), [2]);
+    assert_eq!(controllers.pads(), [Some(2), Some(1)]);
+
+    // free pad indexes in the middle are kept
+    assert!(!controllers.select(Selection::Auto));
+    controllers.set_connected(&[1, 2, 4]);
+    controllers.set_connected(&[2, 4]);
+    assert_eq!(controllers.pads(), [Some(2), None, Some(4)]);
+    controllers.set_connected(&[2]);
+    assert_eq!(controllers.pads(), [Some(2)]);
+
+    assert!(controllers.set_connected(&[]).is_empty());
+    assert!(controllers.pads().is_empty());
+  }
+
+  #[test]
+  fn test_select_without_pads() {
+    let mut controllers = Controllers::new();
+    assert!(!controllers.select(Selection::Handle(1)));
+    controllers.set_connected(&[2, 1]);
+    assert_eq!(controllers.pads(), [Some(1), Some(2)]);
+  }
+}
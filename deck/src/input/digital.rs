@@ -0,0 +1,160 @@
+use crate::{gamepad::XButtons, profile::StickDigital};
+
+/// The directions of each sector as D-pad bits of [`XButtons`],
+/// counterclockwise from the right, 45° apart. Even sectors are cardinal, odd ones diagonal.
+const SECTORS: [u16; 8] = [
+  XButtons::RIGHT,
+  XButtons::UP | XButtons::RIGHT,
+  XButtons::UP,
+  XButtons::UP | XButtons::LEFT,
+  XButtons::LEFT,
+  XButtons::DOWN | XButtons::LEFT,
+  XButtons::DOWN,
+  XButtons::DOWN | XButtons::RIGHT,
+];
+
+/// The state of a stick converted into 4 or 8 digital directions.
+pub struct DigitalStick {
+  config: StickDigital,
+  /// The index of the pressed sector in [`SECTORS`], `None` if released.
+  sector: Option<usize>,
+}
+
+impl DigitalStick {
+  pub fn new(config: &StickDigital) -> Self {
+    Self {
+      config: *config,
+      sector: None,
+    }
+  }
+
+  /// Use the new config, keeping the pressed directions.
+  pub fn set_config(&mut self, config: &StickDigital) {
+    self.config = *config;
+  }
+
+  /// Update with the stick `(x, y)` in `[-1, 1]`, where positive y is up.
+  /// Return the pressed directions as D-pad bits of [`XButtons`].
+  pub fn apply(&mut self, x: f32, y: f32) -> u16 {
+    let threshold = match self.sector {
+      Some(_) => self.config.release,
+      None => self.config.threshold,
+    };
+    if x.hypot(y) < threshold {
+      self.sector = None;
+      return 0;
+    }
+
+    let angle = y.atan2(x).to_degrees();
+    // keep the direction until the stick is clearly in another sector
+    let keep = self
+      .sector
+      .is_some_and(|s| self.margin(s, angle) + self.config.angle_hysteresis > 0.0);
+    if !keep {
+      self.sector = (0..SECTORS.len())
+        // diagonal sectors are empty in 4-way mode
+        .filter(|&s| self.half_width(s) > 0.0)
+        .max_by(|&a, &b| {
+          let (a, b) = (self.margin(a, angle), self.margin(b, angle));
+          a.total_cmp(&b)
+        });
+    }
+    self.sector.map_or(0, |s| SECTORS[s])
+  }
+
+  fn half_width(&self, sector: usize) -> f32 {
+    if sector.is_multiple_of(2) {
+      (90.0 - self.config.diagonal_angle) / 2.0
+    } else {
+      self.config.diagonal_angle / 2.0
+    }
+  }
+
+  /// Degrees from the angle to the nearest border of the sector, positive inside.
+  fn margin(&self, sector: usize, angle: f32) -> f32 {
+    let center = sector as f32 * 45.0;
+    let distance = (angle - center + 180.0).rem_euclid(360.0) - 180.0;
+    self.half_width(sector) - distance.abs()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn stick(diagonal_angle: f32) -> DigitalStick {
+    DigitalStick::new(&StickDigital {
+      diagonal_angle,
+      ..Default::default()
+    })
+  }
+
+  fn at(degrees: f32, magnitude: f32) -> (f32, f32) {
+    let a = degrees.to_radians();
+    (magnitude * a.cos(), magnitude * a.sin())
+  }
+
+  /// Move the stick to the angle at full deflection.
+  fn apply(stick: &mut DigitalStick, degrees: f32) -> u16 {
+    let (x, y) = at(degrees, 1.0);
+    stick.apply(x, y)
+  }
+
+  #[test]
+  fn test_8_way() {
+    let mut s = stick(45.0);
+    assert_eq!(apply(&mut s, 0.0), XButtons::RIGHT);
+    assert_eq!(apply(&mut s, 45.0), XButtons::UP | XButtons::RIGHT);
+    assert_eq!(apply(&mut s, 90.0), XButtons::UP);
+    assert_eq!(apply(&mut s, 180.0), XButtons::LEFT);
+    assert_eq!(apply(&mut s, -135.0), XButtons::DOWN | XButtons::LEFT);
+    assert_eq!(apply(&mut s, 270.0), XButtons::DOWN);
+  }
+
+  #[test]
+  fn test_4_way() {
+    let mut s = stick(0.0);
+    assert_eq!(apply(&mut s, 30.0), XButtons::RIGHT);
+    assert_eq!(apply(&mut s, 60.0), XButtons::UP);
+    // never diagonal
+    let mut s = stick(0.0);
+    assert_eq!(apply(&mut s, 45.0).count_ones(), 1);
+  }
+
+  #[test]
+  fn test_diagonal_angle() {
+    // 30° is diagonal with wide diagonal sectors, cardinal with narrow ones
+    assert_eq!(
+      apply(&mut stick(60.0), 30.0),
+      XButtons::UP | XButtons::RIGHT
+    );
+    assert_eq!(apply(&mut stick(20.0), 30.0), XButtons::RIGHT);
+  }
+
+  #[test]
+  fn test_threshold() {
+    let mut s = stick(45.0);
+    // below the threshold
+    let (x, y) = at(0.0, 0.45);
+    assert_eq!(s.apply(x, y), 0);
+    let (x, y) = at(0.0, 0.6);
+    assert_eq!(s.apply(x, y), XButtons::RIGHT);
+    // held until the deflection drops below the release deflection
+    let (x, y) = at(0.0, 0.45);
+    assert_eq!(s.apply(x, y), XButtons::RIGHT);
+    let (x, y) = at(0.0, 0.35);
+    assert_eq!(s.apply(x, y), 0);
+  }
+
+  #[test]
+  fn test_angle_hysteresis() {
+    let mut s = stick(45.0);
+    assert_eq!(apply(&mut s, 0.0), XButtons::RIGHT);
+    // the border is at 22.5°, so the direction is kept within the hysteresis
+    assert_eq!(apply(&mut s, 25.0), XButtons::RIGHT);
+    assert_eq!(apply(&mut s, 30.0), XButtons::UP | XButtons::RIGHT);
+    // and the same on the way back
+    assert_eq!(apply(&mut s, 20.0), XButtons::UP | XButtons::RIGHT);
+    assert_eq!(apply(&mut s, 15.0), XButtons::RIGHT);
+  }
+}
@@ -0,0 +1,323 @@
+use crate::profile::{FilterConfig, Profile};
+use std::f32::consts::PI;
+
+/// The shortest time step, in seconds, to avoid dividing by zero.
+const MIN_DT: f32 = 1e-4;
+
+/// A smoothing filter of 2D mouse deltas, see [`FilterConfig`].
+/// Filters are linear at slow speed, so the total movement is kept once the input stops.
+pub enum Filter {
+  None,
+  Exponential { alpha: f32, value: [f32; 2] },
+  OneEuro([OneEuro; 2]),
+  SoftTier(Box<SoftTier>),
+}
+
+impl Filter {
+  pub fn new(config: &FilterConfig) -> Self {
+    match *config {
+      FilterConfig::None => Filter::None,
+      FilterConfig::Exponential { alpha } => Filter::Exponential {
+        alpha,
+        value: [0.0; 2],
+      },
+      FilterConfig::OneEuro {
+        min_cutoff,
+        beta,
+        d_cutoff,
+      } => Filter::OneEuro([OneEuro::new(min_cutoff, beta, d_cutoff); 2]),
+      FilterConfig::SoftTier { threshold, window } => {
+        Filter::SoftTier(Box::new(SoftTier::new(threshold, window)))
+      }
+    }
+  }
+
+  /// Use the new parameters, keeping the state if the kind and the window are the same.
+  pub fn set_config(&mut self, config: &FilterConfig) {
+    match (&mut *self, *config) {
+      (Filter::None, FilterConfig::None) => {}
+      (Filter::Exponential { alpha, .. }, FilterConfig::Exponential { alpha: new }) => *alpha = new,
+      (
+        Filter::OneEuro(filters),
+        FilterConfig::OneEuro {
+          min_cutoff,
+          beta,
+          d_cutoff,
+        },
+      ) => {
+        for f in filters {
+          (f.min_cutoff, f.beta, f.d_cutoff) = (min_cutoff, beta, d_cutoff);
+        }
+      }
+      (Filter::SoftTier(f), FilterConfig::SoftTier { threshold, window }) if f.window == window => {
+        f.threshold = threshold;
+      }
+      _ => *self = Filter::new(config),
+    }
+  }
+
+  /// Filter a delta which is read `dt` seconds after the previous one.
+  pub fn apply(&mut self, delta: [f32; 2], dt: f32) -> [f32; 2] {
+    let dt = dt.max(MIN_DT);
+    match self {
+      Filter::None => delta,
+      Filter::Exponential { alpha, value } => {
+        for (v, d) in value.iter_mut().zip(delta) {
+          *v += *alpha * (d - *v);
+        }
+        *value
+      }
+      Filter::OneEuro([x, y]) => [x.apply(delta[0], dt), y.apply(delta[1], dt)],
+      Filter::SoftTier(f) => f.apply(delta, dt),
+    }
+  }
+}
+
+/// The One-Euro filter of one axis, see <https://gery.casiez.net/1euro/>.
+#[derive(Clone, Copy)]
+pub struct OneEuro {
+  min_cutoff: f32,
+  beta: f32,
+  d_cutoff: f32,
+  value: f32,
+  derivative: f32,
+}
+
+impl OneEuro {
+  fn new(min_cutoff: f32, beta: f32, d_cutoff: f32) -> Self {
+    Self {
+      min_cutoff,
+      beta,
+      d_cutoff,
+      value: 0.0,
+      derivative: 0.0,
+    }
+  }
+
+  fn apply(&mut self, x: f32, dt: f32) -> f32 {
+    let derivative = (x - self.value) / dt;
+    self.derivative += smoothing(self.d_cutoff, dt) * (derivative - self.derivative);
+    let cutoff = self.min_cutoff + self.beta * self.derivative.abs();
+    self.value += smoothing(cutoff, dt) * (x - self.value);
+    self.value
+  }
+}
+
+/// The smoothing factor of a low-pass filter with the cutoff frequency in Hz.
+fn smoothing(cutoff: f32, dt: f32) -> f32 {
+  let tau = 1.0 / (2.0 * PI * cutoff);
+  1.0 / (1.0 + tau / dt)
+}
+
+/// Soft tiered smoothing: slow movement is averaged over the last updates,
+/// fast movement is applied directly, and the movement between is split.
+pub struct SoftTier {
+  /// Speed (per second) above which the movement is applied directly.
+  threshold: f32,
+  window: usize,
+  /// The smoothed part of the last `window` deltas, as a ring buffer.
+  history: [[f32; 2]; FilterConfig::MAX_WINDOW],
+  next: usize,
+}
+
+impl SoftTier {
+  fn new(threshold: f32, window: usize) -> Self {
+    Self {
+      threshold,
+      window: window.clamp(1, FilterConfig::MAX_WINDOW),
+      history: [[0.0; 2]; FilterConfig::MAX_WINDOW],
+      next: 0,
+    }
+  }
+
+  fn apply(&mut self, delta: [f32; 2], dt: f32) -> [f32; 2] {
+    let speed = delta[0].hypot(delta[1]) / dt;
+    // the weight of the direct part grows from 0 at half the threshold to 1 at the threshold
+    let lower = self.threshold / 2.0;
+    let direct = if speed >= self.threshold {
+      1.0
+    } else {
+      ((speed - lower) / (self.threshold - lower)).clamp(0.0, 1.0)
+    };
+
+    self.history[self.next] = delta.map(|d| d * (1.0 - direct));
+    self.next = (self.next + 1) % self.window;
+    let history = &self.history[..self.window];
+    let average = |axis: usize| history.iter().map(|h| h[axis]).sum::<f32>() / self.window as f32;
+
+    [
+      delta[0] * direct + average(0),
+      delta[1] * direct + average(1),
+    ]
+  }
+}
+
+/// Motion input of a controller before filtering.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Pointer {
+  /// From `MouseMove`, usually the right trackpad.
+  pub trackpad: [f32; 2],
+  /// From `GyroMove`.
+  pub gyro: [f32; 2],
+  /// `BtnPrecision` is held.
+  pub precision: bool,
+  /// Angular velocity around the pitch, roll and yaw axes in degrees per second,
+  /// from the motion data.
+  pub rot_vel: [f32; 3],
+  /// `BtnRecenter` is held.
+  pub recenter: bool,
+}
+
+/// The filters of the mouse sources of a controller.
+pub struct PointerFilters {
+  trackpad: Filter,
+  gyro: Filter,
+}
+
+impl PointerFilters {
+  /// Create filters with empty state.
+  /// The filters do nothing if smoothing is off in the profile.
+  pub fn new(profile: &Profile) -> Self {
+    let mut filters = Self {
+      trackpad: Filter::None,
+      gyro: Filter::None,
+    };
+    filters.set_profile(profile);
+    filters
+  }
+
+  /// Use the filters of the profile, keeping the state of unchanged filters.
+  pub fn set_profile(&mut self, profile: &Profile) {
+    let config = |config| match profile.smoothing {
+      true => config,
+      false => FilterConfig::None,
+    };
+    self.trackpad.set_config(&config(profile.trackpad_filter));
+    self.gyro.set_config(&config(profile.gyro_filter));
+  }
+
+  /// Filter each source.
+  pub fn apply(&mut self, pointer: &Pointer, dt: f32) -> Pointer {
+    Pointer {
+      trackpad: self.trackpad.apply(pointer.trackpad, dt),
+      gyro: self.gyro.apply(pointer.gyro, dt),
+      ..*pointer
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::profile::FilterKind;
+
+  const DT: f32 = 0.004;
+
+  /// Run a filter over the x deltas, return the output x deltas.
+  fn run(config: FilterConfig, input: &[f32]) -> Vec<f32> {
+    let mut filter = Filter::new(&config);
+    input
+      .iter()
+      .map(|&x| filter.apply([x, 0.0], DT)[0])
+      .collect()
+  }
+
+  /// A slow movement of 0.5 per update with deterministic noise.
+  fn noisy(n: usize) -> Vec<f32> {
+    (0..n)
+      .map(|i| 0.5 + if i % 2 == 0 { 0.4 } else { -0.4 })
+      .collect()
+  }
+
+  fn variance(values: &[f32]) -> f32 {
+    let mean = values.iter().sum::<f32>() / values.len() as f32;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+  }
+
+  #[test]
+  fn test_smooths_noise() {
+    let input = noisy(400);
+    for kind in [
+      FilterKind::Exponential,
+      FilterKind::OneEuro,
+      FilterKind::SoftTier,
+    ] {
+      let output = run(kind.default_config(), &input);
+      // skip the warm-up
+      assert!(
+        variance(&output[200..]) < variance(&input[200..]) / 4.0,
+        "{} doesn't smooth the noise",
+        kind
+      );
+      // the mean movement is kept
+      let mean = output[200..].iter().sum::<f32>() / 200.0;
+      assert!((mean - 0.5).abs() < 0.05, "{}: mean {}", kind, mean);
+    }
+    assert_eq!(run(FilterConfig::None, &input), input);
+  }
+
+  #[test]
+  fn test_keeps_total_movement() {
+    // a short swipe and then nothing
+    let mut input = vec![3.0; 20];
+    input.extend([0.0; 400]);
+    for kind in [FilterKind::Exponential, FilterKind::SoftTier] {
+      let total = run(kind.default_config(), &input).iter().sum::<f32>();
+      assert!((total - 60.0).abs() < 0.01, "{}: total {}", kind, total);
+    }
+  }
+
+  #[test]
+  fn test_fast_movement() {
+    // 10 per update is 2500 per second, above the soft tier threshold
+    let input = [10.0; 5];
+    assert_eq!(
+      run(FilterKind::SoftTier.default_config(), &input),
+      input.to_vec()
+    );
+
+    // the One-Euro filter lags less when the movement speeds up
+    let step = |beta| {
+      let config = FilterConfig::OneEuro {
+        min_cutoff: 2.0,
+        beta,
+        d_cutoff: 1.0,
+      };
+      run(config, &[10.0; 10])[9]
+    };
+    assert!(step(0.1) > step(0.0));
+  }
+
+  #[test]
+  fn test_pointer_filters() {
+    let mut profile = Profile {
+      trackpad_filter: FilterKind::Exponential.default_config(),
+      ..Default::default()
+    };
+    let pointer = Pointer {
+      trackpad: [4.0, -4.0],
+      gyro: [1.0, 1.0],
+      ..Default::default()
+    };
+    let filtered = PointerFilters::new(&profile).apply(&pointer, DT);
+    assert_eq!(filtered.trackpad, [2.0, -2.0]);
+    // the default gyro filter is One-Euro
+    assert!(filtered.gyro[0] < 1.0);
+
+    profile.smoothing = false;
+    assert_eq!(PointerFilters::new(&profile).apply(&pointer, DT), pointer);
+  }
+
+  #[test]
+  fn test_set_config() {
+    let mut filter = Filter::new(&FilterConfig::Exponential { alpha: 0.5 });
+    filter.apply([4.0, 0.0], DT);
+    // the smoothed value is kept with new parameters
+    filter.set_config(&FilterConfig::Exponential { alpha: 0.25 });
+    assert_eq!(filter.apply([0.0, 0.0], DT), [1.5, 0.0]);
+    // and reset with another kind
+    filter.set_config(&FilterConfig::None);
+    filter.set_config(&FilterConfig::Exponential { alpha: 0.25 });
+    assert_eq!(filter.apply([0.0, 0.0], DT), [0.0, 0.0]);
+  }
+}
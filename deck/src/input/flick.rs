@@ -0,0 +1,209 @@
+use crate::profile::FlickStick;
+use std::{
+  f32::consts::{PI, TAU},
+  mem,
+};
+
+/// The stick is released when its deflection drops below this fraction of the threshold,
+/// so it doesn't flick again when it wobbles around the threshold.
+const RELEASE: f32 = 0.8;
+/// The calibration turns 360° in this time.
+const CALIBRATION_TIME: f32 = 1.0;
+
+/// A rotation spread over time.
+#[derive(Default)]
+struct Turn {
+  /// Radians left to turn.
+  remaining: f32,
+  /// Seconds left to turn.
+  time: f32,
+}
+
+impl Turn {
+  /// The radians to turn in the next `dt` seconds.
+  fn step(&mut self, dt: f32) -> f32 {
+    let angle = if self.time <= dt {
+      self.remaining
+    } else {
+      self.remaining * dt / self.time
+    };
+    self.remaining -= angle;
+    self.time = (self.time - dt).max(0.0);
+    angle
+  }
+}
+
+/// The state of the flick stick of a pad, which turns stick deflection into horizontal mouse movement.
+pub struct Flick {
+  config: FlickStick,
+  /// The stick angle of the last update, `None` if the stick is released.
+  angle: Option<f32>,
+  flick: Turn,
+  calibration: Turn,
+  /// Rotation to apply in the next step.
+  rotation: f32,
+  /// The fraction of a pixel which isn't sent yet.
+  remainder: f32,
+}
+
+impl Flick {
+  pub fn new(config: &FlickStick) -> Self {
+    Self {
+      config: *config,
+      angle: None,
+      flick: Turn::default(),
+      calibration: Turn::default(),
+      rotation: 0.0,
+      remainder: 0.0,
+    }
+  }
+
+  /// Use the new config without cancelling a flick or the calibration.
+  pub fn set_config(&mut self, config: &FlickStick) {
+    self.config = *config;
+  }
+
+  /// Update with the stick `(x, y)` in `[-1, 1]`, where positive y is up.
+  pub fn update(&mut self, x: f32, y: f32) {
+    let magnitude = x.hypot(y);
+    // clockwise from up, so right is a positive turn
+    let angle = x.atan2(y);
+    match self.angle {
+      None if magnitude >= self.config.threshold => {
+        // finish the last flick at once
+        self.rotation += self.flick.remaining;
+        self.flick = Turn {
+          remaining: angle,
+          time: self.config.flick_time_ms / 1000.0,
+        };
+        self.angle = Some(angle);
+      }
+      Some(_) if magnitude < self.config.threshold * RELEASE => self.angle = None,
+      Some(last) => {
+        self.rotation += wrap(angle - last);
+        self.angle = Some(angle);
+      }
+      None => {}
+    }
+  }
+
+  /// Turn a full circle in [`CALIBRATION_TIME`].
+  /// If `pixels_per_360` is right, the camera faces the same direction afterwards.
+  pub fn calibrate(&mut self) {
+    self.calibration = Turn {
+      remaining: TAU,
+      time: CALIBRATION_TIME,
+    };
+  }
+
+  /// The mouse movement in pixels in the next `dt` seconds.
+  pub fn step(&mut self, dt: f32) -> i32 {
+    let angle = self.flick.step(dt) + self.calibration.step(dt) + mem::take(&mut self.rotation);
+    let pixels = self.remainder + angle / TAU * self.config.pixels_per_360;
+    let sent = pixels.round();
+    self.remainder = pixels - sent;
+    sent as i32
+  }
+}
+
+/// Wrap an angle into `[-PI, PI]`.
+fn wrap(angle: f32) -> f32 {
+  (angle + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DT: f32 = 0.004;
+
+  /// 5 pixels per degree, flicks take 5 updates.
+  fn flick() -> Flick {
+    Flick::new(&FlickStick {
+      enabled: true,
+      pixels_per_360: 1800.0,
+      threshold: 0.9,
+      flick_time_ms: 20.0,
+    })
+  }
+
+  /// Feed the stick positions and return the total pixels.
+  fn run(flick: &mut Flick, stick: &[(f32, f32)]) -> i32 {
+    stick
+      .iter()
+      .map(|&(x, y)| {
+        flick.update(x, y);
+        flick.step(DT)
+      })
+      .sum()
+  }
+
+  fn at(degrees: f32) -> (f32, f32) {
+    let a = degrees.to_radians();
+    (a.sin(), a.cos())
+  }
+
+  #[test]
+  fn test_flick() {
+    let mut f = flick();
+    // a flick to the right is a quarter turn, spread over the flick time
+    let first = run(&mut f, &[(1.0, 0.0)]);
+    assert!((89..=91).contains(&first));
+    assert_eq!(first + run(&mut f, &[(1.0, 0.0); 10]), 450);
+
+    // flick back after releasing the stick
+    assert_eq!(run(&mut f, &[(0.0, 0.0)]), 0);
+    assert_eq!(run(&mut f, &[(0.0, -1.0); 10]), 900);
+    assert_eq!(run(&mut f, &[(0.0, 0.0)]), 0);
+    assert_eq!(run(&mut f, &[(-0.7, -0.7); 10]), -675);
+
+    // a big flick without spreading is sent at once
+    let mut f = Flick::new(&FlickStick {
+      pixels_per_360: 3600.0,
+      flick_time_ms: 0.0,
+      ..Default::default()
+    });
+    assert_eq!(run(&mut f, &[(0.0, -1.0)]), 1800);
+    assert_eq!(run(&mut f, &[(0.0, -1.0); 20]), 0);
+  }
+
+  #[test]
+  fn test_rotation() {
+    let mut f = flick();
+    assert_eq!(run(&mut f, &[at(0.0); 10]), 0);
+    // rotate clockwise by 90° while the stick keeps the deflection
+    let stick: Vec<_> = (1..=9).map(|i| at(i as f32 * 10.0)).collect();
+    assert_eq!(run(&mut f, &stick), 450);
+    // rotate across the bottom, where the angle wraps
+    let stick: Vec<_> = (10..=17).map(|i| at(i as f32 * 10.0)).collect();
+    assert_eq!(run(&mut f, &stick), 400);
+    assert_eq!(run(&mut f, &[at(190.0)]), 100);
+    assert_eq!(run(&mut f, &[at(170.0)]), -100);
+  }
+
+  #[test]
+  fn test_release() {
+    let mut f = flick();
+    assert_eq!(run(&mut f, &[(0.0, 1.0)]), 0);
+    // still held above the release deflection, then released
+    assert_eq!(run(&mut f, &[(0.0, 0.75), (0.0, 0.5)]), 0);
+    // no flick below the threshold
+    assert_eq!(run(&mut f, &[(0.85, 0.0); 10]), 0);
+    assert_eq!(run(&mut f, &[(0.95, 0.0); 10]), 450);
+  }
+
+  #[test]
+  fn test_calibrate() {
+    let mut f = flick();
+    f.calibrate();
+    let steps = (CALIBRATION_TIME / DT) as usize + 10;
+    assert_eq!(run(&mut f, &vec![(0.0, 0.0); steps]), 1800);
+  }
+
+  #[test]
+  fn test_wrap() {
+    assert!((wrap(1.5 * PI) + 0.5 * PI).abs() < 1e-5);
+    assert!((wrap(-1.5 * PI) - 0.5 * PI).abs() < 1e-5);
+    assert!((wrap(0.25) - 0.25).abs() < 1e-5);
+  }
+}
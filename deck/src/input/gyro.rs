@@ -0,0 +1,204 @@
+use crate::profile::{GyroSteering, GyroStick, SteeringAxis};
+
+/// The shortest time step, in seconds, to avoid dividing by zero.
+const MIN_DT: f32 = 1e-4;
+
+/// Convert a gyro delta read `dt` seconds after the previous one into a right stick deflection,
+/// blended with the physical stick `(thumb_rx, thumb_ry)`.
+/// The gyro moves like a mouse, so positive y (down) deflects the stick down.
+pub fn gyro_to_stick(
+  config: &GyroStick,
+  delta: [f32; 2],
+  dt: f32,
+  (rx, ry): (i16, i16),
+) -> (i16, i16) {
+  let scale = config.sensitivity / 1000.0 / dt.max(MIN_DT);
+  let mut x = delta[0] * scale;
+  let mut y = -delta[1] * scale;
+
+  // skip the deadzone of the game
+  let magnitude = x.hypot(y);
+  if magnitude > 0.0 {
+    let compensated = config.deadzone + (1.0 - config.deadzone) * magnitude.min(1.0);
+    x *= compensated / magnitude;
+    y *= compensated / magnitude;
+  }
+
+  x += config.blend * rx as f32 / i16::MAX as f32;
+  y += config.blend * ry as f32 / i16::MAX as f32;
+
+  // keep the direction when the sum is out of range
+  let magnitude = x.hypot(y);
+  if magnitude > 1.0 {
+    x /= magnitude;
+    y /= magnitude;
+  }
+  (scale_to_i16(x), scale_to_i16(y))
+}
+
+/// The wheel of gyro steering, which integrates the angular velocity into an angle.
+pub struct SteeringWheel {
+  config: GyroSteering,
+  /// Degrees from the center, positive to the right.
+  angle: f32,
+}
+
+impl SteeringWheel {
+  pub fn new(config: &GyroSteering) -> Self {
+    Self {
+      config: *config,
+      angle: 0.0,
+    }
+  }
+
+  /// Use the new config, keeping the angle.
+  pub fn set_config(&mut self, config: &GyroSteering) {
+    self.config = *config;
+  }
+
+  /// Turn by the angular velocity (degrees per second) read `dt` seconds after the previous one.
+  /// Return the left stick x. The wheel stops at the lock like a real wheel,
+  /// so turning back from beyond the lock responds at once.
+  pub fn apply(&mut self, rot_vel: [f32; 3], dt: f32, recenter: bool) -> i16 {
+    if recenter {
+      self.angle = 0.0;
+      return 0;
+    }
+    let velocity = match self.config.axis {
+      SteeringAxis::Roll => rot_vel[1],
+      SteeringAxis::Yaw => rot_vel[2],
+    };
+    let sign = if self.config.invert { -1.0 } else { 1.0 };
+    let half = self.config.lock_to_lock / 2.0;
+    self.angle += sign * velocity * dt;
+    self.angle *= (-self.config.centering * dt).exp();
+    self.angle = self.angle.clamp(-half, half);
+    scale_to_i16(self.angle / half)
+  }
+}
+
+fn scale_to_i16(f: f32) -> i16 {
+  (f * i16::MAX as f32) as i16
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DT: f32 = 0.004;
+
+  fn config(deadzone: f32, blend: f32) -> GyroStick {
+    GyroStick {
+      sensitivity: 1.0,
+      deadzone,
+      blend,
+      trackpad: false,
+    }
+  }
+
+  #[test]
+  fn test_speed() {
+    // 2 per 4ms is 500 per second, half deflection
+    assert_eq!(
+      gyro_to_stick(&config(0.0, 0.0), [2.0, 0.0], DT, (0, 0)),
+      (16383, 0)
+    );
+    // y is flipped, and the deflection is limited
+    assert_eq!(
+      gyro_to_stick(&config(0.0, 0.0), [0.0, 100.0], DT, (0, 0)),
+      (0, -i16::MAX)
+    );
+    // no movement, no deflection
+    assert_eq!(
+      gyro_to_stick(&config(0.2, 0.0), [0.0, 0.0], DT, (0, 0)),
+      (0, 0)
+    );
+  }
+
+  #[test]
+  fn test_deadzone() {
+    // a tiny movement is lifted to the deadzone
+    let (x, y) = gyro_to_stick(&config(0.2, 0.0), [0.001, 0.0], DT, (0, 0));
+    assert!((6553..=6560).contains(&x));
+    assert_eq!(y, 0);
+    // half speed is mapped into the middle of the range outside the deadzone
+    assert_eq!(
+      gyro_to_stick(&config(0.2, 0.0), [2.0, 0.0], DT, (0, 0)),
+      (19660, 0)
+    );
+  }
+
+  fn steering(centering: f32) -> SteeringWheel {
+    SteeringWheel::new(&GyroSteering {
+      enabled: true,
+      axis: SteeringAxis::Roll,
+      lock_to_lock: 180.0,
+      centering,
+      invert: false,
+    })
+  }
+
+  /// Turn at the velocity for the updates, return the last output.
+  fn turn(wheel: &mut SteeringWheel, roll: f32, updates: usize) -> i16 {
+    (0..updates)
+      .map(|_| wheel.apply([0.0, roll, 0.0], DT, false))
+      .last()
+      .unwrap()
+  }
+
+  #[test]
+  fn test_steering() {
+    let mut wheel = steering(0.0);
+    // 45° to the right in 1s is half the way to the lock
+    let half = 16370..=16390;
+    assert!(half.contains(&turn(&mut wheel, 45.0, 250)));
+    // the wheel stops at the lock
+    assert_eq!(turn(&mut wheel, 90.0, 500), i16::MAX);
+    assert!(half.contains(&turn(&mut wheel, -45.0, 250)));
+    // recentered
+    assert_eq!(wheel.apply([0.0, 0.0, 0.0], DT, true), 0);
+    assert_eq!(turn(&mut wheel, 0.0, 10), 0);
+
+    // other axes are ignored
+    assert_eq!(wheel.apply([90.0, 0.0, 90.0], DT, false), 0);
+    // inverted, around the yaw axis
+    let mut wheel = SteeringWheel::new(&GyroSteering {
+      axis: SteeringAxis::Yaw,
+      invert: true,
+      ..Default::default()
+    });
+    assert!(wheel.apply([0.0, 0.0, 90.0], DT, false) < 0);
+  }
+
+  #[test]
+  fn test_centering() {
+    let mut wheel = steering(1.0);
+    let turned = turn(&mut wheel, 45.0, 250);
+    // less than without centering
+    assert!((10000..i16::MAX / 2).contains(&turned));
+    // and returns towards the center when the Deck is still
+    assert!(turn(&mut wheel, 0.0, 250) < turned / 2);
+  }
+
+  #[test]
+  fn test_blend() {
+    let stick = (i16::MAX / 2, 0);
+    // the stick alone
+    assert_eq!(
+      gyro_to_stick(&config(0.0, 1.0), [0.0, 0.0], DT, stick),
+      (16383, 0)
+    );
+    assert_eq!(
+      gyro_to_stick(&config(0.0, 0.5), [0.0, 0.0], DT, stick),
+      (8191, 0)
+    );
+    // the stick is ignored
+    assert_eq!(
+      gyro_to_stick(&config(0.0, 0.0), [0.0, 0.0], DT, stick),
+      (0, 0)
+    );
+    // the sum is limited to the unit circle
+    let (x, y) = gyro_to_stick(&config(0.0, 1.0), [0.0, -4.0], DT, (i16::MAX, 0));
+    assert!((23160..=23170).contains(&x) && (23160..=23170).contains(&y));
+  }
+}
@@ -0,0 +1,336 @@
+use super::{
+  digital::DigitalStick,
+  filter::{Pointer, PointerFilters},
+  flick::Flick,
+  gyro::{gyro_to_stick, SteeringWheel},
+  sensitivity::MouseScaler,
+};
+use crate::{
+  gamepad::{XButtons, XGamepad},
+  profile::{GyroMode, GyroStick, Profile, StickOutput},
+};
+use std::mem;
+use stickdeck_common::Keys;
+
+/// Motion processing state of a pad: smoothing, the gyro mode, mouse sensitivity, flick stick
+/// and the digital stick outputs.
+pub struct PadMotion {
+  filters: PointerFilters,
+  scaler: MouseScaler,
+  gyro_mode: GyroMode,
+  gyro_stick: GyroStick,
+  flick_stick: bool,
+  flick: Flick,
+  /// `None` if gyro steering is off.
+  steering: Option<SteeringWheel>,
+  recenter: bool,
+  /// The outputs of the left and right stick.
+  outputs: [StickOutput; 2],
+  digital: [DigitalStick; 2],
+}
+
+impl PadMotion {
+  pub fn new(profile: &Profile) -> Self {
+    Self {
+      filters: PointerFilters::new(profile),
+      scaler: MouseScaler::new(&profile.sensitivity),
+      gyro_mode: profile.gyro_mode,
+      gyro_stick: profile.gyro_stick,
+      flick_stick: profile.flick_stick.enabled,
+      flick: Flick::new(&profile.flick_stick),
+      steering: profile
+        .steering
+        .enabled
+        .then(|| SteeringWheel::new(&profile.steering)),
+      recenter: false,
+      outputs: [profile.left_stick, profile.right_stick],
+      digital: [
+        DigitalStick::new(&profile.stick_digital),
+        DigitalStick::new(&profile.stick_digital),
+      ],
+    }
+  }
+
+  /// Apply the new settings, keeping the state of unchanged filters, the fractions of pixels,
+  /// a flick or calibration in progress, the steering angle unless steering is toggled
+  /// and the pressed directions of the sticks whose output is unchanged.
+  pub fn set_profile(&mut self, profile: &Profile) {
+    let mut motion = Self::new(profile);
+    mem::swap(&mut motion.filters, &mut self.filters);
+    mem::swap(&mut motion.scaler, &mut self.scaler);
+    mem::swap(&mut motion.flick, &mut self.flick);
+    if let (Some(wheel), Some(new)) = (&mut self.steering, &mut motion.steering) {
+      mem::swap(wheel, new);
+    }
+    for (i, digital) in self.digital.iter_mut().enumerate() {
+      if self.outputs[i] == motion.outputs[i] {
+        mem::swap(digital, &mut motion.digital[i]);
+      }
+    }
+    *self = motion;
+    self.filters.set_profile(profile);
+    self.scaler.set_config(&profile.sensitivity);
+    self.flick.set_config(&profile.flick_stick);
+    if let Some(wheel) = &mut self.steering {
+      wheel.set_config(&profile.steering);
+    }
+    for digital in &mut self.digital {
+      digital.set_config(&profile.stick_digital);
+    }
+  }
+
+  /// Center the gyro steering in the next update.
+  pub fn recenter(&mut self) {
+    self.recenter = true;
+  }
+
+  /// Turn a full circle with the flick stick calibration.
+  pub fn calibrate(&mut self) {
+    self.flick.calibrate();
+  }
+
+  /// Convert the sticks which don't output a stick into D-pad buttons or keys.
+  /// The converted sticks are centered. Return the pressed keys.
+  pub fn sticks(&mut self, gamepad: &mut XGamepad) -> Keys {
+    let mut keys = Keys::default();
+    let sticks = [
+      (&mut gamepad.thumb_lx, &mut gamepad.thumb_ly),
+      (&mut gamepad.thumb_rx, &mut gamepad.thumb_ry),
+    ];
+    for ((output, digital), (x, y)) in self.outputs.iter().zip(&mut self.digital).zip(sticks) {
+      // the keys of up, down, left and right, `None` for the D-pad
+      let mapping = match output {
+        StickOutput::Stick => continue,
+        StickOutput::Dpad => None,
+        StickOutput::Wasd => Some([Keys::W, Keys::S, Keys::A, Keys::D]),
+        StickOutput::Arrows => Some([Keys::UP, Keys::DOWN, Keys::LEFT, Keys::RIGHT]),
+      };
+      let max = i16::MAX as f32;
+      let directions = digital.apply(*x as f32 / max, *y as f32 / max);
+      (*x, *y) = (0, 0);
+      match mapping {
+        None => gamepad.buttons.raw |= directions,
+        Some(mapping) => {
+          let buttons = [
+            XButtons::UP,
+            XButtons::DOWN,
+            XButtons::LEFT,
+            XButtons::RIGHT,
+          ];
+          for (button, key) in buttons.into_iter().zip(mapping) {
+            if directions & button != 0 {
+              keys.press(key);
+            }
+          }
+        }
+      }
+    }
+    keys
+  }
+
+  /// Apply the movement read `dt` seconds after the previous one.
+  /// Return the mouse movement in pixels, which may not fit in a packet. The right stick of
+  /// the gamepad may be replaced by the flick stick or deflected by the gyro, and the left
+  /// stick x by gyro steering.
+  pub fn apply(&mut self, pointer: &Pointer, dt: f32, gamepad: &mut XGamepad) -> [i32; 2] {
+    if let Some(wheel) = &mut self.steering {
+      let recenter = pointer.recenter || self.recenter;
+      gamepad.thumb_lx = wheel.apply(pointer.rot_vel, dt, recenter);
+    }
+    self.recenter = false;
+
+    if self.flick_stick {
+      let max = i16::MAX as f32;
+      self
+        .flick
+        .update(gamepad.thumb_rx as f32 / max, gamepad.thumb_ry as f32 / max);
+      (gamepad.thumb_rx, gamepad.thumb_ry) = (0, 0);
+    }
+    // the calibration turns even if the flick stick is off
+    let flick = self.flick.step(dt);
+
+    let p = self.filters.apply(pointer, dt);
+    let mut mouse = p.trackpad;
+    match self.gyro_mode {
+      GyroMode::Off => {}
+      GyroMode::Mouse => {
+        mouse[0] += p.gyro[0];
+        mouse[1] += p.gyro[1];
+      }
+      GyroMode::RightStick => {
+        let mut stick = p.gyro;
+        if self.gyro_stick.trackpad {
+          stick[0] += mouse[0];
+          stick[1] += mouse[1];
+          mouse = [0.0; 2];
+        }
+        let physical = (gamepad.thumb_rx, gamepad.thumb_ry);
+        (gamepad.thumb_rx, gamepad.thumb_ry) = gyro_to_stick(&self.gyro_stick, stick, dt, physical);
+      }
+    }
+    let [x, y] = self.scaler.apply(mouse, dt, pointer.precision);
+    [x + flick, y]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::profile::FlickStick;
+
+  const DT: f32 = 0.004;
+
+  #[test]
+  fn test_gyro_mode() {
+    let mut profile = Profile {
+      smoothing: false,
+      ..Default::default()
+    };
+    let pointer = Pointer {
+      trackpad: [4.0, -4.0],
+      gyro: [1.0, 1.0],
+      ..Default::default()
+    };
+    let apply = |profile: &Profile| {
+      let mut gamepad = XGamepad {
+        thumb_rx: 100,
+        ..Default::default()
+      };
+      let mouse = PadMotion::new(profile).apply(&pointer, DT, &mut gamepad);
+      (mouse, gamepad.thumb_rx, gamepad.thumb_ry)
+    };
+
+    // the gyro is off by default
+    assert_eq!(apply(&profile), ([4, -4], 100, 0));
+
+    profile.gyro_mode = GyroMode::Mouse;
+    assert_eq!(apply(&profile), ([5, -3], 100, 0));
+
+    profile.gyro_mode = GyroMode::RightStick;
+    let (mouse, rx, ry) = apply(&profile);
+    assert_eq!(mouse, [4, -4]);
+    assert!(rx > 100 && ry < 0);
+
+    profile.gyro_stick.trackpad = true;
+    let (mouse, rx, _) = apply(&profile);
+    assert_eq!(mouse, [0, 0]);
+    assert!(rx > 100);
+  }
+
+  #[test]
+  fn test_flick_stick() {
+    let profile = Profile {
+      flick_stick: FlickStick {
+        enabled: true,
+        flick_time_ms: 0.0,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let mut motion = PadMotion::new(&profile);
+    let mut gamepad = XGamepad {
+      thumb_rx: i16::MAX,
+      ..Default::default()
+    };
+    // the stick turns the mouse instead, a quarter turn together with the trackpad
+    let pointer = Pointer {
+      trackpad: [4.0, 0.0],
+      ..Default::default()
+    };
+    let moved: i32 = (0..10)
+      .map(|_| motion.apply(&pointer, DT, &mut gamepad)[0])
+      .sum();
+    assert_eq!(moved, 900 + 4 * 10);
+    assert_eq!((gamepad.thumb_rx, gamepad.thumb_ry), (0, 0));
+  }
+
+  #[test]
+  fn test_steering() {
+    let mut profile = Profile::default();
+    let pointer = Pointer {
+      rot_vel: [0.0, 90.0, 0.0],
+      ..Default::default()
+    };
+    let mut gamepad = XGamepad {
+      thumb_lx: -100,
+      ..Default::default()
+    };
+    // the left stick is kept if steering is off
+    PadMotion::new(&profile).apply(&pointer, DT, &mut gamepad);
+    assert_eq!(gamepad.thumb_lx, -100);
+
+    profile.steering.enabled = true;
+    let mut motion = PadMotion::new(&profile);
+    motion.apply(&pointer, DT, &mut gamepad);
+    assert!(gamepad.thumb_lx > 0);
+    motion.recenter();
+    motion.apply(&Pointer::default(), DT, &mut gamepad);
+    assert_eq!(gamepad.thumb_lx, 0);
+  }
+
+  #[test]
+  fn test_set_profile() {
+    let mut profile = Profile::default();
+    profile.steering.enabled = true;
+    let mut motion = PadMotion::new(&profile);
+    let mut gamepad = XGamepad::default();
+    let turn = Pointer {
+      rot_vel: [0.0, 90.0, 0.0],
+      ..Default::default()
+    };
+    motion.apply(&turn, DT, &mut gamepad);
+    let angle = gamepad.thumb_lx;
+    assert!(angle > 0);
+
+    // unrelated settings keep the steering angle
+    profile.sensitivity.multiplier = 2.0;
+    motion.set_profile(&profile);
+    motion.apply(&Pointer::default(), DT, &mut gamepad);
+    assert_eq!(gamepad.thumb_lx, angle);
+
+    // turning steering off and on starts from the center
+    profile.steering.enabled = false;
+    motion.set_profile(&profile);
+    profile.steering.enabled = true;
+    motion.set_profile(&profile);
+    motion.apply(&Pointer::default(), DT, &mut gamepad);
+    assert_eq!(gamepad.thumb_lx, 0);
+  }
+
+  #[test]
+  fn test_stick_output() {
+    let profile = Profile {
+      left_stick: StickOutput::Wasd,
+      right_stick: StickOutput::Dpad,
+      ..Default::default()
+    };
+    let mut motion = PadMotion::new(&profile);
+    let mut gamepad = XGamepad {
+      thumb_lx: i16::MAX,
+      thumb_ly: i16::MAX,
+      thumb_rx: 0,
+      thumb_ry: -i16::MAX,
+      ..Default::default()
+    };
+    let keys = motion.sticks(&mut gamepad);
+    assert_eq!(keys, Keys(Keys::W | Keys::D));
+    assert_eq!(gamepad.buttons.raw, XButtons::DOWN);
+    // the converted sticks are centered
+    let sticks = (
+      gamepad.thumb_lx,
+      gamepad.thumb_ly,
+      gamepad.thumb_rx,
+      gamepad.thumb_ry,
+    );
+    assert_eq!(sticks, (0, 0, 0, 0));
+
+    // the sticks are kept by default
+    let mut gamepad = XGamepad {
+      thumb_ly: i16::MAX,
+      ..Default::default()
+    };
+    let keys = PadMotion::new(&Profile::default()).sticks(&mut gamepad);
+    assert_eq!(keys, Keys::default());
+    assert_eq!(gamepad.thumb_ly, i16::MAX);
+  }
+}
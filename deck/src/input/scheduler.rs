@@ -0,0 +1,160 @@
+use std::{
+  fmt, thread,
+  time::{Duration, Instant},
+};
+
+/// Upper bounds of the jitter histogram buckets in microseconds.
+/// The last bucket counts the rest.
+const JITTER_BUCKETS_US: [u64; 7] = [50, 100, 250, 500, 1000, 2000, 5000];
+
+/// Histogram of the difference between the actual period and the configured interval.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Jitter {
+  counts: [u64; JITTER_BUCKETS_US.len() + 1],
+  sum: Duration,
+  max: Duration,
+}
+
+impl Jitter {
+  fn record(&mut self, jitter: Duration) {
+    let us = jitter.as_micros() as u64;
+    let bucket = JITTER_BUCKETS_US
+      .iter()
+      .position(|&bound| us < bound)
+      .unwrap_or(JITTER_BUCKETS_US.len());
+    self.counts[bucket] += 1;
+    self.sum += jitter;
+    self.max = self.max.max(jitter);
+  }
+
+  pub fn count(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  pub fn mean(&self) -> Duration {
+    match self.count() {
+      0 => Duration::ZERO,
+      n => self.sum / n as u32,
+    }
+  }
+}
+
+impl fmt::Display for Jitter {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "mean {}us, max {}us |",
+      self.mean().as_micros(),
+      self.max.as_micros()
+    )?;
+    for (bound, count) in JITTER_BUCKETS_US.iter().zip(self.counts.iter()) {
+      write!(f, " <{}us: {}", bound, count)?;
+    }
+    write!(
+      f,
+      " >={}us: {}",
+      JITTER_BUCKETS_US[JITTER_BUCKETS_US.len() - 1],
+      self.counts[JITTER_BUCKETS_US.len()]
+    )
+  }
+}
+
+/// Run ticks at a fixed interval. The sleep time is shortened by the work time,
+/// so the period doesn't drift above the interval.
+pub struct Scheduler {
+  interval: Duration,
+  deadline: Instant,
+  last_tick: Instant,
+  jitter: Jitter,
+}
+
+impl Scheduler {
+  pub fn new(interval: Duration) -> Self {
+    let now = Instant::now();
+    Self {
+      interval,
+      deadline: now,
+      last_tick: now,
+      jitter: Jitter::default(),
+    }
+  }
+
+  /// Change the interval and reset the statistics.
+  pub fn set_interval(&mut self, interval: Duration) {
+    self.interval = interval;
+    self.jitter = Jitter::default();
+  }
+
+  pub fn jitter(&self) -> &Jitter {
+    &self.jitter
+  }
+
+  /// Sleep until the next tick.
+  pub fn wait(&mut self) {
+    let sleep = self.advance(Instant::now());
+    if !sleep.is_zero() {
+      thread::sleep(sleep);
+    }
+    self.tick(Instant::now());
+  }
+
+  /// Move the deadline to the next tick and return how long to sleep until it.
+  fn advance(&mut self, now: Instant) -> Duration {
+    self.deadline += self.interval;
+    if self.deadline <= now {
+      // overrun, skip the missed ticks instead of catching up with a burst
+      self.deadline = now;
+      return Duration::ZERO;
+    }
+    self.deadline - now
+  }
+
+  /// Record the period since the last tick.
+  fn tick(&mut self, now: Instant) {
+    let period = now - self.last_tick;
+    self.last_tick = now;
+    self.jitter.record(period.abs_diff(self.interval));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_deadline() {
+    let ms = Duration::from_millis;
+    let mut scheduler = Scheduler::new(ms(3));
+    let start = scheduler.deadline;
+
+    // the work time is subtracted from the sleep time
+    assert_eq!(scheduler.advance(start + ms(1)), ms(2));
+    // deadlines don't drift when waking up late
+    assert_eq!(scheduler.advance(start + ms(4)), ms(2));
+    // overrun
+    assert_eq!(scheduler.advance(start + ms(20)), Duration::ZERO);
+    assert_eq!(scheduler.advance(start + ms(21)), ms(2));
+  }
+
+  #[test]
+  fn test_jitter() {
+    let mut scheduler = Scheduler::new(Duration::from_millis(3));
+    let start = scheduler.last_tick;
+    scheduler.tick(start + Duration::from_micros(3010));
+    scheduler.tick(start + Duration::from_micros(6000));
+    scheduler.tick(start + Duration::from_micros(15000));
+
+    let jitter = scheduler.jitter();
+    assert_eq!(jitter.count(), 3);
+    assert_eq!(jitter.counts[0], 2);
+    assert_eq!(jitter.counts[JITTER_BUCKETS_US.len()], 1);
+    assert_eq!(jitter.max, Duration::from_millis(6));
+    assert_eq!(
+      jitter.to_string(),
+      "mean 2006us, max 6000us | <50us: 2 <100us: 0 <250us: 0 <500us: 0 <1000us: 0 <2000us: 0 <5000us: 0 >=5000us: 1"
+    );
+
+    scheduler.set_interval(Duration::from_millis(1));
+    assert_eq!(scheduler.jitter().count(), 0);
+  }
+}
@@ -0,0 +1,112 @@
+use crate::profile::Sensitivity;
+
+/// The shortest time step, in seconds, to avoid dividing by zero.
+const MIN_DT: f32 = 1e-4;
+
+/// Scale mouse movement by the [`Sensitivity`] and convert it to whole pixels.
+/// The fractions are carried to the next update, so slow movement isn't lost.
+pub struct MouseScaler {
+  sensitivity: Sensitivity,
+  remainder: [f32; 2],
+}
+
+impl MouseScaler {
+  pub fn new(sensitivity: &Sensitivity) -> Self {
+    Self {
+      sensitivity: *sensitivity,
+      remainder: [0.0; 2],
+    }
+  }
+
+  /// Use the new sensitivity, keeping the remainder.
+  pub fn set_config(&mut self, sensitivity: &Sensitivity) {
+    self.sensitivity = *sensitivity;
+  }
+
+  /// The multiplier of movement at the speed (per second), from the acceleration curve.
+  pub fn gain(&self, speed: f32) -> f32 {
+    let s = &self.sensitivity;
+    if s.acceleration <= 0.0 {
+      return 1.0;
+    }
+    let accel = (s.acceleration * (speed - s.offset).max(0.0)).powf(s.exponent);
+    (1.0 + accel).min(s.max_gain)
+  }
+
+  /// Scale a delta read `dt` seconds after the previous one, in whole pixels.
+  pub fn apply(&mut self, delta: [f32; 2], dt: f32, precision: bool) -> [i32; 2] {
+    let s = &self.sensitivity;
+    let speed = delta[0].hypot(delta[1]) / dt.max(MIN_DT);
+    let mut multiplier = s.multiplier * self.gain(speed);
+    if precision {
+      multiplier *= s.precision;
+    }
+    let scaled = [
+      delta[0] * multiplier * s.scale_x,
+      delta[1] * multiplier * s.scale_y,
+    ];
+
+    let mut out = [0; 2];
+    for ((o, r), v) in out.iter_mut().zip(&mut self.remainder).zip(scaled) {
+      let total = v + *r;
+      let whole = total.trunc();
+      *r = total - whole;
+      *o = whole as i32;
+    }
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DT: f32 = 0.004;
+
+  #[test]
+  fn test_remainder() {
+    let mut scaler = MouseScaler::new(&Sensitivity {
+      multiplier: 0.5,
+      ..Default::default()
+    });
+    // 0.6 * 0.5 = 0.3 per update, which is a pixel every 3 or 4 updates
+    let moved: Vec<_> = (0..10)
+      .map(|_| scaler.apply([0.6, -0.6], DT, false))
+      .collect();
+    assert_eq!(moved.iter().map(|m| m[0]).sum::<i32>(), 3);
+    assert_eq!(moved.iter().map(|m| m[1]).sum::<i32>(), -3);
+
+    // large movement is kept, it's split into packets when sending
+    assert_eq!(scaler.apply([1000.0, 0.0], DT, false), [500, 0]);
+  }
+
+  #[test]
+  fn test_scale() {
+    let mut scaler = MouseScaler::new(&Sensitivity {
+      multiplier: 2.0,
+      scale_x: 1.5,
+      scale_y: 0.5,
+      precision: 0.25,
+      ..Default::default()
+    });
+    assert_eq!(scaler.apply([10.0, 10.0], DT, false), [30, 10]);
+    assert_eq!(scaler.apply([10.0, 10.0], DT, true), [7, 2]);
+  }
+
+  #[test]
+  fn test_acceleration() {
+    let scaler = MouseScaler::new(&Sensitivity {
+      acceleration: 0.01,
+      offset: 100.0,
+      exponent: 2.0,
+      max_gain: 3.0,
+      ..Default::default()
+    });
+    assert_eq!(scaler.gain(50.0), 1.0);
+    assert_eq!(scaler.gain(200.0), 2.0);
+    assert_eq!(scaler.gain(1000.0), 3.0);
+
+    // acceleration is off by default
+    assert_eq!(MouseScaler::new(&Sensitivity::default()).gain(1000.0), 1.0);
+  }
+}
@@ -24,6 +24,9 @@ pub struct XBoxControls {
   pub btn_y: InputDigitalAction,
   pub btn_l_mouse: InputDigitalAction,
   pub btn_r_mouse: InputDigitalAction,
+  /// `None` if the installed action manifest is older than the action.
+  pub btn_precision: Option<InputDigitalAction>,
+  pub btn_recenter: Option<InputDigitalAction>,
   // analog actions
   pub lt: InputAnalogAction,
   pub rt: InputAnalogAction,
@@ -31,10 +34,12 @@ pub struct XBoxControls {
   pub r_move: InputAnalogAction,
   pub mouse_move: InputAnalogAction,
   pub mouse_scroll: InputAnalogAction,
+  /// `None` if the installed action manifest is older than the action.
+  pub gyro_move: Option<InputAnalogAction>,
 }
 
 impl XBoxControls {
-  /// Return `Ok` if all handles are valid.
+  /// Return `Ok` if all handles are valid, except optional actions.
   pub fn new(input: &Input<ClientManager>) -> Result<Self, Error> {
     Ok(Self {
       handle: check_handle(input.get_action_set_handle("XBoxControls"))?,
@@ -55,6 +60,8 @@ impl XBoxControls {
       btn_y: InputDigitalAction::new(input, "BtnY")?,
       btn_l_mouse: InputDigitalAction::new(input, "BtnLeftMouse")?,
       btn_r_mouse: InputDigitalAction::new(input, "BtnRightMouse")?,
+      btn_precision: InputDigitalAction::new(input, "BtnPrecision").ok(),
+      btn_recenter: InputDigitalAction::new(input, "BtnRecenter").ok(),
 
       lt: InputAnalogAction::new(input, "LeftTrigger")?,
       rt: InputAnalogAction::new(input, "RightTrigger")?,
@@ -62,6 +69,7 @@ impl XBoxControls {
       r_move: InputAnalogAction::new(input, "RightMove")?,
       mouse_move: InputAnalogAction::new(input, "MouseMove")?,
       mouse_scroll: InputAnalogAction::new(input, "MouseScroll")?,
+      gyro_move: InputAnalogAction::new(input, "GyroMove").ok(),
     })
   }
 }
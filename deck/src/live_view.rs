@@ -0,0 +1,259 @@
+use crate::{
+  gamepad::{XButtons, XGamepad},
+  input::Snapshot,
+};
+use iced::{
+  alignment::{Horizontal, Vertical},
+  mouse,
+  theme::Palette,
+  widget::canvas::{self, Frame, Geometry, Path, Stroke, Text},
+  Color, Point, Rectangle, Renderer, Size, Theme, Vector,
+};
+use std::collections::VecDeque;
+
+/// Max number of mouse movements in the trail.
+pub const TRAIL_LEN: usize = 32;
+
+/// XInput's recommended deadzones, drawn for reference.
+const LEFT_DEADZONE: i16 = 7849;
+const RIGHT_DEADZONE: i16 = 8689;
+
+/// Buttons in the order of the lights, 4 per row.
+const BUTTONS: [(u16, &str); 14] = [
+  (XButtons::UP, "Up"),
+  (XButtons::DOWN, "Down"),
+  (XButtons::LEFT, "Left"),
+  (XButtons::RIGHT, "Right"),
+  (XButtons::A, "A"),
+  (XButtons::B, "B"),
+  (XButtons::X, "X"),
+  (XButtons::Y, "Y"),
+  (XButtons::LB, "LB"),
+  (XButtons::RB, "RB"),
+  (XButtons::LTHUMB, "LS"),
+  (XButtons::RTHUMB, "RS"),
+  (XButtons::BACK, "Back"),
+  (XButtons::START, "Start"),
+];
+
+/// Space around each part of the view.
+const MARGIN: f32 = 8.0;
+
+/// Draw the first pad and the mouse movement of an input [`Snapshot`]:
+/// sticks, triggers, buttons and the mouse trail from left to right.
+pub struct LiveView<'a> {
+  pub snapshot: &'a Snapshot,
+  /// Recent mouse movements, the latest at the back.
+  pub trail: &'a VecDeque<Vector>,
+}
+
+impl<Message> canvas::Program<Message> for LiveView<'_> {
+  type State = ();
+
+  fn draw(
+    &self,
+    _state: &Self::State,
+    renderer: &Renderer,
+    theme: &Theme,
+    bounds: Rectangle,
+    _cursor: mouse::Cursor,
+  ) -> Vec<Geometry> {
+    let mut frame = Frame::new(renderer, bounds.size());
+    let palette = theme.palette();
+
+    // 5 square cells
+    let size = (bounds.width / 5.0).min(bounds.height);
+    let cell = |i: usize| Rectangle::new(Point::new(i as f32 * size, 0.0), Size::new(size, size));
+
+    let default = XGamepad::default();
+    let gamepad = self.snapshot.gamepad.as_ref().unwrap_or(&default);
+    let pressed = |button: u16| gamepad.buttons.raw & button != 0;
+
+    draw_stick(
+      &mut frame,
+      &palette,
+      cell(0),
+      (gamepad.thumb_lx, gamepad.thumb_ly),
+      LEFT_DEADZONE,
+      pressed(XButtons::LTHUMB),
+    );
+    draw_stick(
+      &mut frame,
+      &palette,
+      cell(1),
+      (gamepad.thumb_rx, gamepad.thumb_ry),
+      RIGHT_DEADZONE,
+      pressed(XButtons::RTHUMB),
+    );
+    draw_triggers(
+      &mut frame,
+      &palette,
+      cell(2),
+      (gamepad.left_trigger, gamepad.right_trigger),
+    );
+
+    // button lights
+    let area = cell(3);
+    let light = Size::new(size / 4.0 - MARGIN / 2.0, size / 4.0 - MARGIN / 2.0);
+    for (i, (button, label)) in BUTTONS.iter().enumerate() {
+      let top_left = Point::new(
+        area.x + (i % 4) as f32 * size / 4.0,
+        area.y + (i / 4) as f32 * size / 4.0,
+      );
+      draw_light(
+        &mut frame,
+        &palette,
+        top_left,
+        light,
+        label,
+        pressed(*button),
+      );
+    }
+
+    draw_trail(&mut frame, &palette, cell(4), self.snapshot, self.trail);
+
+    vec![frame.into_geometry()]
+  }
+}
+
+fn outline(palette: &Palette) -> Stroke<'static> {
+  Stroke::default().with_color(palette.text).with_width(2.0)
+}
+
+fn dimmed(color: Color) -> Color {
+  Color { a: 0.2, ..color }
+}
+
+/// Draw a stick as a dot in a circle, with the deadzone filled.
+/// The dot is highlighted when the stick is pressed.
+fn draw_stick(
+  frame: &mut Frame,
+  palette: &Palette,
+  area: Rectangle,
+  (x, y): (i16, i16),
+  deadzone: i16,
+  pressed: bool,
+) {
+  let center = area.center();
+  let radius = area.width / 2.0 - MARGIN;
+  let scale = |v: i16| v as f32 / i16::MAX as f32 * radius;
+
+  frame.stroke(&Path::circle(center, radius), outline(palette));
+  frame.fill(&Path::circle(center, scale(deadzone)), dimmed(palette.text));
+  // the y axis of the stick points up
+  let position = center + Vector::new(scale(x), -scale(y));
+  frame.fill(
+    &Path::circle(position, radius / 8.0),
+    if pressed {
+      palette.primary
+    } else {
+      palette.text
+    },
+  );
+}
+
+/// Draw the left and right triggers as bars filled from the bottom.
+fn draw_triggers(frame: &mut Frame, palette: &Palette, area: Rectangle, (lt, rt): (u8, u8)) {
+  let width = area.width / 2.0 - MARGIN * 2.0;
+  let height = area.height - MARGIN * 2.0;
+  for (i, value) in [lt, rt].into_iter().enumerate() {
+    let x = area.x + MARGIN + i as f32 * (width + MARGIN * 2.0);
+    let filled = height * value as f32 / u8::MAX as f32;
+    frame.fill_rectangle(
+      Point::new(x, area.y + MARGIN + height - filled),
+      Size::new(width, filled),
+      palette.primary,
+    );
+    frame.stroke(
+      &Path::rectangle(Point::new(x, area.y + MARGIN), Size::new(width, height)),
+      outline(palette),
+    );
+  }
+}
+
+/// Draw a labeled light, filled when it's on.
+fn draw_light(
+  frame: &mut Frame,
+  palette: &Palette,
+  top_left: Point,
+  size: Size,
+  label: &str,
+  on: bool,
+) {
+  let rect = Path::rectangle(top_left, size);
+  if on {
+    frame.fill(&rect, palette.primary);
+  } else {
+    frame.stroke(&rect, outline(palette));
+  }
+  frame.fill_text(Text {
+    content: label.into(),
+    position: top_left + Vector::new(size.width / 2.0, size.height / 2.0),
+    color: if on { palette.background } else { palette.text },
+    size: 16.0,
+    horizontal_alignment: Horizontal::Center,
+    vertical_alignment: Vertical::Center,
+    ..Text::default()
+  });
+}
+
+/// Draw the recent mouse movements as a line ending at the center,
+/// with the mouse buttons in the top corners.
+fn draw_trail(
+  frame: &mut Frame,
+  palette: &Palette,
+  area: Rectangle,
+  snapshot: &Snapshot,
+  trail: &VecDeque<Vector>,
+) {
+  let inner = Rectangle::new(
+    Point::new(area.x + MARGIN, area.y + MARGIN),
+    Size::new(area.width - MARGIN * 2.0, area.height - MARGIN * 2.0),
+  );
+  frame.stroke(
+    &Path::rectangle(inner.position(), inner.size()),
+    outline(palette),
+  );
+
+  let clamp = |p: Point| {
+    Point::new(
+      p.x.clamp(inner.x, inner.x + inner.width),
+      p.y.clamp(inner.y, inner.y + inner.height),
+    )
+  };
+  let path = Path::new(|builder| {
+    let mut point = inner.center();
+    builder.move_to(point);
+    // walk back from the latest movement
+    for delta in trail.iter().rev() {
+      point = point - *delta;
+      builder.line_to(clamp(point));
+    }
+  });
+  frame.stroke(
+    &path,
+    Stroke::default()
+      .with_color(palette.primary)
+      .with_width(2.0),
+  );
+  frame.fill(&Path::circle(inner.center(), 4.0), palette.text);
+
+  let button = Size::new(inner.width / 4.0, inner.height / 8.0);
+  let buttons = &snapshot.mouse_buttons;
+  draw_light(
+    frame,
+    palette,
+    inner.position(),
+    button,
+    "L",
+    buttons.is_left_button_down(),
+  );
+  draw_light(
+    frame,
+    palette,
+    Point::new(inner.x + inner.width - button.width, inner.y),
+    button,
+    "R",
+    buttons.is_right_button_down(),
+  );
+}
@@ -1,22 +1,49 @@
+mod api;
+mod binding;
 mod config;
+mod dashboard;
 mod error;
 mod gamepad;
 mod input;
+mod live_view;
+mod network;
+mod profile;
 mod server;
+mod setup;
 mod utils;
+mod vdf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::Config;
+use dashboard::Dashboard;
+use error::Error;
+use gamepad::XGamepad;
 use iced::{
   alignment::Horizontal,
   executor, time,
-  widget::{button, column, slider, text, toggler},
-  window, Application, Command, Element, Length, Settings, Theme,
+  widget::{
+    button, canvas, column, pick_list, qr_code, row, scrollable, slider, text, text_input, toggler,
+    QRCode,
+  },
+  window, Alignment, Application, Color, Command, Element, Length, Settings, Theme, Vector,
 };
-use input::InputConfig;
-use local_ip_address::local_ip;
-use std::{env, net::IpAddr, sync::mpsc};
-use stickdeck_common::perf;
+use input::{Control, Event, InputConfig, Selection, Snapshot};
+use live_view::LiveView;
+use log::error;
+use network::{Bind, BindOption};
+use profile::{FilterKind, FlickStick, GyroMode, GyroSteering, Profile, Sensitivity, StickOutput};
+use serde::Deserialize;
+use server::{ClientInfo, Server};
+use std::{
+  collections::VecDeque,
+  env, mem,
+  net::SocketAddr,
+  path::{Path, PathBuf},
+  process,
+  sync::mpsc,
+  time::Instant,
+};
+use stickdeck_common::{metrics, perf, with_port, ConnectUri, Packet, Role};
 use tokio::sync::watch;
 
 /// Turn your Steam Deck into a joystick for your PC, with trackpad and gyro support!
@@ -26,6 +53,41 @@ struct Args {
   /// Port to bind the server to
   #[arg(short, long, default_value = "7777")]
   port: u16,
+
+  /// Reverse mode: connect to the client (`stickdeck-win --listen`) at `host` or `host:port`,
+  /// instead of waiting for it. The port defaults to `--port`
+  #[arg(long, value_name = "ADDR")]
+  connect: Option<String>,
+
+  /// Serve a local HTTP/JSON API to control the server, at 127.0.0.1:7778 or the given address
+  #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = api::DEFAULT_ADDR)]
+  api: Option<String>,
+
+  /// Path to the config file [default: $XDG_CONFIG_HOME/stickdeck/config.json]
+  #[arg(short, long)]
+  config: Option<PathBuf>,
+
+  /// Steam App ID to run as [default: the one in steam_appid.txt, or 480]
+  #[arg(long, global = true)]
+  app_id: Option<u32>,
+
+  #[command(subcommand)]
+  command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+  /// Install the StickDeck action set and the default layout into the Steam client,
+  /// and write steam_appid.txt for the App ID
+  Setup {
+    /// Remove the installed action set instead
+    #[arg(long)]
+    uninstall: bool,
+
+    /// Print what would be done without changing any file
+    #[arg(long)]
+    dry_run: bool,
+  },
 }
 
 fn main() {
@@ -34,22 +96,87 @@ fn main() {
   }
   env_logger::init();
 
-  let (input_config_tx, input_config_rx) = mpsc::channel();
-  input::spawn(input_config_rx).expect("Failed to spawn the input thread");
-
   let args = Args::parse();
+
+  // Steam reads steam_appid.txt from the working directory, so do we
+  let app_dir = Path::new(".");
+  let app_id = args
+    .app_id
+    .or_else(|| setup::read_app_id(app_dir))
+    .unwrap_or(setup::DEFAULT_APP_ID);
+
+  if let Some(Commands::Setup { uninstall, dry_run }) = args.command {
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    if let Err(e) = setup::run(&home, app_dir, app_id, uninstall, dry_run) {
+      eprintln!("Setup failed: {}", e);
+      process::exit(1);
+    }
+    return;
+  }
+
+  let (config, config_error) = Config::init(args.config.unwrap_or_else(Config::default_path));
+  if let Some(e) = &config_error {
+    error!("{}", e);
+  }
+
+  let api = args.api.map(|addr| {
+    api::spawn(&addr).unwrap_or_else(|e| {
+      error!("Failed to serve the API at {}: {}", addr, e);
+      process::exit(1);
+    })
+  });
+
+  let (control_tx, control_rx) = mpsc::channel();
+  let (event_tx, event_rx) = mpsc::channel();
+  let (connected_tx, connected_rx) = mpsc::channel();
+  let (ui_tx, ui_rx) = watch::channel(Snapshot::default());
+  if let Err(e) = input::spawn(
+    app_id,
+    InputConfig {
+      profile: config.profile().clone(),
+      control_rx,
+      event_tx,
+      ui_tx,
+      connected_rx,
+      ui_update_interval_ms: UI_UPDATE_INTERVAL_MS as u128,
+    },
+  ) {
+    error!("Failed to spawn the input thread: {}", e);
+    process::exit(1);
+  }
+
   App::run(Settings::with_flags(Flags {
-    input_config_tx,
-    config: Config::init(),
+    control_tx,
+    event_rx,
+    connected_tx,
+    ui_rx,
+    config,
+    config_error,
     port: args.port,
+    connect: args.connect,
+    api,
   }))
   .expect("Failed to run the app");
 }
 
+const UI_UPDATE_INTERVAL_MS: u64 = 30;
+/// How often to refresh the metrics in the UI, so they are readable.
+const METRICS_UPDATE_INTERVAL_MS: u128 = 1000;
+
 struct Flags {
-  input_config_tx: mpsc::Sender<InputConfig>,
+  /// Control the input thread.
+  control_tx: mpsc::Sender<Control>,
+  event_rx: mpsc::Receiver<Event>,
+  /// Cloned into each started server to hand connected clients to the input thread.
+  connected_tx: mpsc::Sender<mpsc::SyncSender<Packet<XGamepad>>>,
+  ui_rx: watch::Receiver<Snapshot>,
   config: Config,
+  config_error: Option<Error>,
   port: u16,
+  /// The client address from `--connect`, which overrides the reverse mode in the config
+  /// without saving it.
+  connect: Option<String>,
+  api: Option<api::Api<Message>>,
 }
 
 enum State {
@@ -57,26 +184,100 @@ enum State {
   Started,
 }
 
-#[derive(Debug, Clone)]
+/// Messages of the UI, which can also be posted to the API as JSON.
+#[derive(Deserialize, Debug, Clone)]
 enum Message {
   SetDarkMode(bool),
-  SetDebugMode(bool),
+  SetShowLiveView(bool),
+  SetShowMetrics(bool),
   SetInputUpdateInterval(u64),
+  SetFixedRate(bool),
+  /// Turn the smoothing filters on or off, to compare with the raw input.
+  SetSmoothing(bool),
+  /// Use the filter with its default parameters for the trackpad mouse.
+  SetTrackpadFilter(FilterKind),
+  SetGyroFilter(FilterKind),
+  SetGyroMode(GyroMode),
+  SetMouseSensitivity(f32),
+  SetFlickStick(bool),
+  SetPixelsPer360(f32),
+  /// Turn a full circle with the flick stick, to check the pixels per 360°.
+  CalibrateFlickStick,
+  SetSteering(bool),
+  SetLockToLock(f32),
+  RecenterSteering,
+  SetLeftStickOutput(StickOutput),
+  SetRightStickOutput(StickOutput),
+  SelectProfile(String),
+  SetProfileName(String),
+  SelectController(Selection),
+  CreateProfile,
+  DuplicateProfile,
+  RenameProfile,
+  DeleteProfile,
+  SetPort(String),
+  SetBind(BindOption),
+  /// List the network interfaces again, e.g. after plugging in a USB-Ethernet adapter.
+  RefreshInterfaces,
+  SetReverse(bool),
+  SetReverseAddress(String),
+  /// Save the edited reverse mode address.
+  SubmitReverseAddress,
   StartServer,
+  /// Start the server on a port picked by the OS.
+  StartServerOnFreePort,
+  StopServer,
+  /// Hand the controller role to the client with the id.
+  SetController(u64),
   Update,
   Exit,
 }
 
 struct App {
   flags: Flags,
-  local_ip: IpAddr,
+  /// Bind options with the available interface addresses.
+  bind_options: Vec<BindOption>,
   port: u16,
+  /// The port input on the home screen.
+  port_input: String,
   state: State,
-  content: String,
-  ui_tx: watch::Sender<String>,
-  ui_rx: watch::Receiver<String>,
-  ui_update_interval_ms: u64,
-  debug: bool,
+  server: Option<Server>,
+  /// Whether the last start failed because the address can't be bound.
+  bind_failed: bool,
+  /// The latest input snapshot from the input thread.
+  snapshot: Snapshot,
+  /// Recent mouse movements for the live view, the latest at the back.
+  trail: VecDeque<Vector>,
+  show_live_view: bool,
+  show_metrics: bool,
+  /// Formatted metrics, refreshed every [`METRICS_UPDATE_INTERVAL_MS`].
+  metrics: String,
+  last_metrics: Instant,
+  /// The name input for profile operations.
+  profile_name: String,
+  /// Sequence number of the last [`Control::Apply`] sent to the input thread.
+  sent_seq: u64,
+  /// Sequence number of the last [`Control::Apply`] acknowledged by the input thread.
+  applied_seq: u64,
+  /// The last error to show to the user.
+  error: Option<String>,
+  controller_selection: Selection,
+  /// Connected controllers reported by the input thread.
+  controllers: Vec<u64>,
+  /// Controllers indexed by the pad index.
+  pads: Vec<Option<u64>>,
+  /// Clients connected to the server.
+  clients: Vec<ClientInfo>,
+  dashboard: Dashboard,
+  /// The URI for the client to connect to the server, with its QR code.
+  connect_uri: Option<(ConnectUri, qr_code::State)>,
+  /// The last connection error of the server.
+  server_error: Option<String>,
+  /// Reverse mode, from the config or overridden by `--connect`.
+  reverse: bool,
+  reverse_address: String,
+  /// Whether the reverse mode address is edited but not saved yet.
+  reverse_address_edited: bool,
 }
 
 impl Application for App {
@@ -86,18 +287,38 @@ impl Application for App {
   type Theme = Theme;
 
   fn new(flags: Self::Flags) -> (App, Command<Self::Message>) {
-    let (ui_tx, ui_rx) = watch::channel("".to_string());
     (
       App {
-        local_ip: local_ip().expect("Failed to get local ip address"),
+        bind_options: network::bind_options(),
         port: flags.port,
+        port_input: flags.port.to_string(),
         state: State::Home,
-        content: "".into(),
-        ui_tx,
-        ui_rx,
+        server: None,
+        bind_failed: false,
+        snapshot: Snapshot::default(),
+        trail: VecDeque::with_capacity(live_view::TRAIL_LEN),
+        error: flags.config_error.as_ref().map(|e| e.to_string()),
+        show_live_view: false,
+        show_metrics: false,
+        metrics: String::new(),
+        last_metrics: Instant::now(),
+        profile_name: "".into(),
+        sent_seq: 0,
+        applied_seq: 0,
+        controller_selection: Selection::Auto,
+        controllers: Vec::new(),
+        pads: Vec::new(),
+        clients: Vec::new(),
+        dashboard: Dashboard::new(Instant::now(), input_updates()),
+        server_error: None,
+        connect_uri: None,
+        reverse: flags.connect.is_some() || flags.config.reverse,
+        reverse_address: flags
+          .connect
+          .clone()
+          .unwrap_or_else(|| flags.config.reverse_address.clone()),
+        reverse_address_edited: false,
         flags,
-        debug: false,
-        ui_update_interval_ms: 30,
       },
       window::maximize(true),
     )
@@ -116,7 +337,8 @@ impl Application for App {
   }
 
   fn view(&self) -> Element<'_, Message> {
-    match self.state {
+    // the settings don't fit the 800px tall screen of the Deck
+    let content = match self.state {
       State::Home => column![
         button(
           text("Exit")
@@ -127,107 +349,304 @@ impl Application for App {
         .on_press(Message::Exit)
         .width(Length::Fill),
         column![
-          text(format!(
-            "Input Update Interval: {}ms",
-            self.flags.config.input_update_interval_ms
-          ))
-          .size(20),
-          slider(
-            1.0..=50.0,
-            self.flags.config.input_update_interval_ms as f64,
-            |v| { Message::SetInputUpdateInterval(v as u64) }
+          self.profile_selector(),
+          row![
+            text_input("Profile name", &self.profile_name)
+              .on_input(Message::SetProfileName)
+              .size(20),
+            button(text("New").size(20)).on_press(Message::CreateProfile),
+            button(text("Duplicate").size(20)).on_press(Message::DuplicateProfile),
+            button(text("Rename").size(20)).on_press(Message::RenameProfile),
+            button(text("Delete").size(20)).on_press(Message::DeleteProfile),
+          ]
+          .spacing(8),
+          self.controller_selector(),
+          self.settings_view(),
+          toggler(
+            Some("Reverse Mode (connect to the PC)".into()),
+            self.reverse,
+            Message::SetReverse
           )
-          .height(40)
-          .step(1.0),
-          toggler(Some("Dark Mode".into()), self.flags.config.dark, |v| {
-            Message::SetDarkMode(v)
-          })
           .size(40)
-          .text_size(40)
+          .text_size(40),
+          self.address_input(),
         ]
+        .spacing(8)
         .padding([16, 0]),
-        button(
-          text("Start Server")
-            .size(30)
-            .horizontal_alignment(Horizontal::Center)
-            .width(Length::Fill)
-        )
-        .on_press(Message::StartServer)
-        .width(Length::Fill),
-        text(format!("stickdeck v{}", clap::crate_version!())).size(20)
+        self.start_buttons(),
+        self.error_text(),
+        text(match &self.flags.api {
+          Some(api) => format!(
+            "stickdeck v{} | API at http://{}",
+            clap::crate_version!(),
+            api.addr
+          ),
+          None => format!("stickdeck v{}", clap::crate_version!()),
+        })
+        .size(20)
       ]
-      .padding([40, 80])
-      .into(),
+      .padding([40, 80]),
       State::Started => column![
-        button(
-          text("Exit")
-            .size(30)
-            .horizontal_alignment(Horizontal::Center)
-            .width(Length::Fill)
-        )
-        .on_press(Message::Exit)
-        .width(Length::Fill),
+        row![
+          button(
+            text("Exit")
+              .size(30)
+              .horizontal_alignment(Horizontal::Center)
+              .width(Length::Fill)
+          )
+          .on_press(Message::Exit)
+          .width(Length::Fill),
+          button(
+            text("Stop Server")
+              .size(30)
+              .horizontal_alignment(Horizontal::Center)
+              .width(Length::Fill)
+          )
+          .on_press(Message::StopServer)
+          .width(Length::Fill),
+        ]
+        .spacing(8),
         toggler(
-          Some("Show Debug Info (will leak memory)".into()),
-          self.debug,
-          |v| { Message::SetDebugMode(v) }
+          Some("Show Live View".into()),
+          self.show_live_view,
+          Message::SetShowLiveView
         )
         .size(40)
         .text_size(40),
-        text(format!(
-          "=== [stickdeck v{}] Server is listening at {}:{} ===",
-          clap::crate_version!(),
-          self.local_ip,
-          self.port
-        ))
-        .size(20),
-        // TODO: show content will cause memory leak, fix it
-        text(if self.debug { &self.content } else { "" }).size(16)
+        self.live_view(),
+        toggler(Some("Show Metrics".into()), self.show_metrics, |v| {
+          Message::SetShowMetrics(v)
+        })
+        .size(40)
+        .text_size(40),
+        text(if self.show_metrics { &self.metrics } else { "" }).size(16),
+        column![
+          self.controller_selector(),
+          self.profile_selector(),
+          self.settings_view(),
+          text(if self.applied_seq == self.sent_seq {
+            "Settings applied"
+          } else {
+            "Applying settings..."
+          })
+          .size(16),
+        ]
+        .spacing(8),
+        text(self.server_status()).size(20),
+        self.connect_uri_view(),
+        self.clients_view(),
+        self.dashboard_view(),
+        self.error_text(),
       ]
-      .padding([40, 80])
-      .into(),
-    }
+      .padding([40, 80]),
+    };
+    scrollable(content)
+      .width(Length::Fill)
+      .height(Length::Fill)
+      .into()
   }
 
   fn subscription(&self) -> iced::Subscription<Self::Message> {
-    time::every(time::Duration::from_millis(self.ui_update_interval_ms)).map(|_| Message::Update)
+    time::every(time::Duration::from_millis(UI_UPDATE_INTERVAL_MS)).map(|_| Message::Update)
   }
 
   fn update(&mut self, message: Self::Message) -> Command<Self::Message> {
     match message {
       Message::SetDarkMode(dark) => {
         self.flags.config.dark = dark;
-        self.flags.config.save();
+        self.save_config();
+      }
+      Message::SetShowLiveView(show) => {
+        self.show_live_view = show;
       }
-      Message::SetDebugMode(debug) => {
-        self.debug = debug;
+      Message::SetShowMetrics(show) => {
+        self.show_metrics = show;
+        self.metrics = metrics::snapshot().to_string();
+        self.last_metrics = Instant::now();
       }
       Message::SetInputUpdateInterval(interval) => {
-        self.flags.config.input_update_interval_ms = interval;
-        self.flags.config.save();
+        self.flags.config.profile_mut().input_update_interval_ms = interval;
+        self.apply_profile();
       }
-      Message::StartServer => {
-        let (connected_tx, connected_rx) = mpsc::channel();
-
-        server::spawn(&format!("{}:{}", self.local_ip, self.port), connected_tx);
-
-        self
-          .flags
-          .input_config_tx
-          .send(InputConfig {
-            interval_ms: self.flags.config.input_update_interval_ms,
-            ui_tx: self.ui_tx.clone(),
-            connected_rx,
-            ui_update_interval_ms: self.ui_update_interval_ms as u128,
-          })
-          .expect("Failed to send config to the input thread");
-
-        self.state = State::Started;
+      Message::SetFixedRate(fixed_rate) => {
+        self.flags.config.profile_mut().fixed_rate = fixed_rate;
+        self.apply_profile();
+      }
+      Message::SetSmoothing(smoothing) => {
+        self.flags.config.profile_mut().smoothing = smoothing;
+        self.apply_profile();
+      }
+      Message::SetTrackpadFilter(kind) => {
+        self.flags.config.profile_mut().trackpad_filter = kind.default_config();
+        self.apply_profile();
+      }
+      Message::SetGyroFilter(kind) => {
+        self.flags.config.profile_mut().gyro_filter = kind.default_config();
+        self.apply_profile();
+      }
+      Message::SetGyroMode(mode) => {
+        self.flags.config.profile_mut().gyro_mode = mode;
+        self.apply_profile();
+      }
+      Message::SetMouseSensitivity(multiplier) => {
+        self.flags.config.profile_mut().sensitivity.multiplier = multiplier;
+        self.apply_profile();
+      }
+      Message::SetFlickStick(enabled) => {
+        self.flags.config.profile_mut().flick_stick.enabled = enabled;
+        self.apply_profile();
+      }
+      Message::SetPixelsPer360(pixels) => {
+        let range = FlickStick::PIXELS_PER_360_RANGE;
+        self.flags.config.profile_mut().flick_stick.pixels_per_360 =
+          pixels.clamp(*range.start(), *range.end());
+        self.apply_profile();
+      }
+      Message::CalibrateFlickStick => {
+        self.send_control(Control::CalibrateFlickStick);
+      }
+      Message::SetSteering(enabled) => {
+        self.flags.config.profile_mut().steering.enabled = enabled;
+        self.apply_profile();
+      }
+      Message::SetLockToLock(degrees) => {
+        self.flags.config.profile_mut().steering.lock_to_lock = degrees;
+        self.apply_profile();
+      }
+      Message::RecenterSteering => {
+        self.send_control(Control::RecenterSteering);
+      }
+      Message::SetLeftStickOutput(output) => {
+        self.flags.config.profile_mut().left_stick = output;
+        self.apply_profile();
+      }
+      Message::SetRightStickOutput(output) => {
+        self.flags.config.profile_mut().right_stick = output;
+        self.apply_profile();
+      }
+      Message::SelectProfile(name) => {
+        let res = self.flags.config.select_profile(&name);
+        self.update_profiles(res);
+      }
+      Message::SetProfileName(name) => {
+        self.profile_name = name;
+      }
+      Message::CreateProfile => {
+        let res = self.flags.config.create_profile(&self.profile_name);
+        self.update_profiles(res);
+      }
+      Message::DuplicateProfile => {
+        let res = self.flags.config.duplicate_profile(&self.profile_name);
+        self.update_profiles(res);
+      }
+      Message::RenameProfile => {
+        let res = self.flags.config.rename_profile(&self.profile_name);
+        self.update_profiles(res);
+      }
+      Message::DeleteProfile => {
+        let res = self.flags.config.delete_profile();
+        self.update_profiles(res);
+      }
+      Message::SelectController(selection) => {
+        self.controller_selection = selection;
+        self.send_control(Control::SelectController(selection));
+      }
+      Message::SetPort(port) => {
+        if let Ok(p) = port.parse() {
+          self.port = p;
+        }
+        self.port_input = port;
+      }
+      Message::SetBind(option) => {
+        self.flags.config.bind = option.bind;
+        self.save_config();
+      }
+      Message::RefreshInterfaces => {
+        self.bind_options = network::bind_options();
+      }
+      Message::SetReverse(reverse) => {
+        self.reverse = reverse;
+        self.flags.config.reverse = reverse;
+        self.save_config();
+      }
+      Message::SetReverseAddress(addr) => {
+        self.reverse_address = addr;
+        self.reverse_address_edited = true;
+      }
+      Message::SubmitReverseAddress => self.save_reverse_address(),
+      Message::StartServer if self.reverse => self.start_reverse(),
+      Message::StartServer => self.start_server(self.port),
+      Message::StartServerOnFreePort => self.start_server(0),
+      Message::StopServer => {
+        if let Some(server) = self.server.take() {
+          server.stop();
+        }
+        self.clients.clear();
+        self.server_error = None;
+        self.connect_uri = None;
+        self.state = State::Home;
+      }
+      Message::SetController(id) => {
+        if let Some(server) = &self.server {
+          server.set_controller(id);
+        }
       }
       Message::Update => {
-        self.content = perf!("ui update", self.ui_rx.borrow().clone(), 100);
+        let api_messages: Vec<_> = match &self.flags.api {
+          Some(api) => api.message_rx.try_iter().collect(),
+          None => Vec::new(),
+        };
+        for event in self.flags.event_rx.try_iter() {
+          match event {
+            Event::Applied(seq) => self.applied_seq = seq,
+            Event::ControllersChanged { connected, pads } => {
+              self.controllers = connected;
+              self.pads = pads;
+            }
+          }
+        }
+        if let Some(server) = &self.server {
+          self.clients = server.clients();
+          self.server_error = server.last_error();
+        }
+        self
+          .dashboard
+          .sample(Instant::now(), input_updates(), &self.clients);
+        if self.show_metrics
+          && self.last_metrics.elapsed().as_millis() >= METRICS_UPDATE_INTERVAL_MS
+        {
+          self.metrics = metrics::snapshot().to_string();
+          self.last_metrics = Instant::now();
+        }
+        if self.flags.ui_rx.has_changed().unwrap_or(false) {
+          self.snapshot = perf!(
+            "ui update",
+            self.flags.ui_rx.borrow_and_update().clone(),
+            100
+          );
+          if self.trail.len() == live_view::TRAIL_LEN {
+            self.trail.pop_front();
+          }
+          self.trail.push_back(Vector::new(
+            self.snapshot.mouse_dx as f32,
+            self.snapshot.mouse_dy as f32,
+          ));
+        }
+
+        // handle API messages like UI messages, then publish the new status
+        let commands: Vec<_> = api_messages
+          .into_iter()
+          .map(|message| self.update(message))
+          .collect();
+        if let Some(api) = &self.flags.api {
+          api.status_tx.send_replace(self.api_status());
+        }
+        return Command::batch(commands);
       }
       Message::Exit => {
+        if let Some(server) = self.server.take() {
+          server.stop();
+        }
         std::process::exit(0);
       }
     }
@@ -235,3 +654,558 @@ impl Application for App {
     Command::none()
   }
 }
+
+impl App {
+  /// Clamp the active profile, save the config and send the profile to the input thread.
+  fn apply_profile(&mut self) {
+    // messages from the API aren't limited by the UI controls
+    self.flags.config.profile_mut().validate();
+    self.save_config();
+    self.sent_seq += 1;
+    self.send_control(Control::Apply {
+      seq: self.sent_seq,
+      profile: self.flags.config.profile().clone(),
+    });
+  }
+
+  /// Send the control to the input thread, or show an error if the thread has exited.
+  fn send_control(&mut self, control: Control) {
+    if let Err(e) = self.flags.control_tx.send(control) {
+      error!("Failed to send {:?} to the input thread", e.0);
+      self.error = Some("The input thread has exited, restart StickDeck to apply changes".into());
+    }
+  }
+
+  fn start_server(&mut self, port: u16) {
+    let addr = SocketAddr::new(self.flags.config.bind.ip(), port);
+    match server::spawn(&addr.to_string(), self.flags.connected_tx.clone()) {
+      Ok(server) => {
+        self.dashboard = Dashboard::new(Instant::now(), input_updates());
+        self.port = server.local_addr().port();
+        self.port_input = self.port.to_string();
+        self.connect_uri = connect_uri(server.local_addr());
+        self.server = Some(server);
+        self.bind_failed = false;
+        self.error = None;
+        self.state = State::Started;
+      }
+      Err(e) => {
+        error!("Failed to bind to address {}: {}", addr, e);
+        self.bind_failed = true;
+        self.error = Some(format!("Failed to bind to address {}: {}", addr, e));
+      }
+    }
+  }
+
+  /// Connect to the client in reverse mode.
+  fn start_reverse(&mut self) {
+    self.save_reverse_address();
+    let addr = with_port(&self.reverse_address, self.port);
+    match server::connect(&addr, self.flags.connected_tx.clone()) {
+      Ok(server) => {
+        self.dashboard = Dashboard::new(Instant::now(), input_updates());
+        // the client doesn't need to connect in reverse mode
+        self.connect_uri = None;
+        self.server = Some(server);
+        self.error = None;
+        self.state = State::Started;
+      }
+      Err(e) => {
+        error!("Failed to connect to {}: {}", addr, e);
+        self.error = Some(format!("Failed to connect to {}: {}", addr, e));
+      }
+    }
+  }
+
+  /// Save the reverse mode address if it's edited in the UI.
+  fn save_reverse_address(&mut self) {
+    if mem::take(&mut self.reverse_address_edited) {
+      self.flags.config.reverse_address = self.reverse_address.clone();
+      self.save_config();
+    }
+  }
+
+  /// The client address in reverse mode, or the address and port to listen on.
+  fn address_input(&self) -> Element<'_, Message> {
+    let port = row![
+      text("Port:").size(20),
+      text_input("Port", &self.port_input)
+        .on_input(Message::SetPort)
+        .size(20),
+    ]
+    .spacing(8)
+    .align_items(Alignment::Center);
+
+    if self.reverse {
+      row![
+        text("PC Address:").size(20),
+        text_input("host or host:port", &self.reverse_address)
+          .on_input(Message::SetReverseAddress)
+          .on_submit(Message::SubmitReverseAddress)
+          .size(20),
+        port,
+      ]
+      .spacing(8)
+      .align_items(Alignment::Center)
+      .into()
+    } else {
+      let selected = self
+        .bind_options
+        .iter()
+        .find(|o| o.bind == self.flags.config.bind)
+        .cloned()
+        // the address of an unplugged interface
+        .unwrap_or(BindOption {
+          bind: self.flags.config.bind,
+          interface: None,
+        });
+      row![
+        text("Listen on:").size(20),
+        pick_list(self.bind_options.clone(), Some(selected), Message::SetBind).text_size(20),
+        button(text("Refresh").size(20)).on_press(Message::RefreshInterfaces),
+        port,
+      ]
+      .spacing(8)
+      .align_items(Alignment::Center)
+      .into()
+    }
+  }
+
+  fn server_status(&self) -> String {
+    let version = clap::crate_version!();
+    match &self.server {
+      Some(server) if server.is_reverse() => {
+        if self.clients.is_empty() {
+          format!(
+            "=== [stickdeck v{}] Connecting to {} ... ===",
+            version,
+            server.local_addr()
+          )
+        } else {
+          format!(
+            "=== [stickdeck v{}] Connected to {} ===",
+            version,
+            server.local_addr()
+          )
+        }
+      }
+      Some(server) if server.local_addr().ip().is_unspecified() => format!(
+        "=== [stickdeck v{}] Server is listening at port {} on all interfaces ===",
+        version,
+        server.local_addr().port()
+      ),
+      Some(server) => format!(
+        "=== [stickdeck v{}] Server is listening at {} ===",
+        version,
+        server.local_addr()
+      ),
+      None => format!("=== [stickdeck v{}] Server is stopped ===", version),
+    }
+  }
+
+  /// Apply the result of a profile operation.
+  fn update_profiles(&mut self, res: Result<(), Error>) {
+    match res {
+      Ok(()) => {
+        self.error = None;
+        self.profile_name.clear();
+        self.apply_profile();
+      }
+      Err(e) => self.error = Some(e.to_string()),
+    }
+  }
+
+  /// Settings of the active profile, shared by the home screen and the started screen.
+  fn settings_view(&self) -> Element<'_, Message> {
+    let profile = self.flags.config.profile();
+    let interval = profile.input_update_interval_ms;
+    let interval_range = Profile::INPUT_UPDATE_INTERVAL_MS_RANGE;
+    let sensitivity = profile.sensitivity.multiplier;
+    column![
+      text(format!("Input Update Interval: {}ms", interval)).size(20),
+      slider(
+        *interval_range.start() as f64..=*interval_range.end() as f64,
+        interval as f64,
+        |v| { Message::SetInputUpdateInterval(v as u64) }
+      )
+      .height(40)
+      .step(1.0),
+      toggler(
+        Some("Fixed Rate (send the gamepad state every update)".into()),
+        profile.fixed_rate,
+        Message::SetFixedRate
+      )
+      .size(40)
+      .text_size(40),
+      row![
+        text("Gyro:").size(20),
+        pick_list(
+          &GyroMode::ALL[..],
+          Some(profile.gyro_mode),
+          Message::SetGyroMode
+        )
+        .text_size(20),
+        text("Trackpad Smoothing:").size(20),
+        pick_list(
+          &FilterKind::ALL[..],
+          Some(profile.trackpad_filter.kind()),
+          Message::SetTrackpadFilter
+        )
+        .text_size(20),
+        text("Gyro Smoothing:").size(20),
+        pick_list(
+          &FilterKind::ALL[..],
+          Some(profile.gyro_filter.kind()),
+          Message::SetGyroFilter
+        )
+        .text_size(20),
+      ]
+      .spacing(8)
+      .align_items(Alignment::Center),
+      text(format!("Mouse Sensitivity: {:.2}x", sensitivity)).size(20),
+      slider(Sensitivity::MULTIPLIER_RANGE, sensitivity, |v| {
+        Message::SetMouseSensitivity(v)
+      })
+      .height(40)
+      .step(0.05),
+      toggler(
+        Some("Smoothing (turn off to compare with the raw input)".into()),
+        profile.smoothing,
+        Message::SetSmoothing
+      )
+      .size(40)
+      .text_size(40),
+      self.flick_stick_view(),
+      self.steering_view(),
+      row![
+        text("Left Stick:").size(20),
+        pick_list(
+          &StickOutput::ALL[..],
+          Some(profile.left_stick),
+          Message::SetLeftStickOutput
+        )
+        .text_size(20),
+        text("Right Stick:").size(20),
+        pick_list(
+          &StickOutput::ALL[..],
+          Some(profile.right_stick),
+          Message::SetRightStickOutput
+        )
+        .text_size(20),
+      ]
+      .spacing(8)
+      .align_items(Alignment::Center),
+      toggler(Some("Dark Mode".into()), self.flags.config.dark, |v| {
+        Message::SetDarkMode(v)
+      })
+      .size(40)
+      .text_size(40)
+    ]
+    .spacing(8)
+    .into()
+  }
+
+  /// The flick stick toggle, and the calibration when it's on.
+  fn flick_stick_view(&self) -> Element<'_, Message> {
+    let flick_stick = self.flags.config.profile().flick_stick;
+    let toggle = toggler(
+      Some("Flick Stick (the right stick turns the camera)".into()),
+      flick_stick.enabled,
+      Message::SetFlickStick,
+    )
+    .size(40)
+    .text_size(40);
+    if !flick_stick.enabled {
+      return toggle.into();
+    }
+
+    let pixels = flick_stick.pixels_per_360;
+    column![
+      toggle,
+      row![
+        text(format!("Pixels per 360°: {:.0}", pixels)).size(20),
+        slider(FlickStick::PIXELS_PER_360_RANGE, pixels, |v| {
+          Message::SetPixelsPer360(v)
+        })
+        .step(10.0),
+        button(text("-1").size(20)).on_press(Message::SetPixelsPer360(pixels - 1.0)),
+        button(text("+1").size(20)).on_press(Message::SetPixelsPer360(pixels + 1.0)),
+        button(text("Turn 360°").size(20)).on_press(Message::CalibrateFlickStick),
+      ]
+      .spacing(8)
+      .align_items(Alignment::Center)
+    ]
+    .spacing(8)
+    .into()
+  }
+
+  /// The gyro steering toggle, and the wheel settings when it's on.
+  fn steering_view(&self) -> Element<'_, Message> {
+    let steering = self.flags.config.profile().steering;
+    let toggle = toggler(
+      Some("Gyro Steering (tilt to steer with the left stick)".into()),
+      steering.enabled,
+      Message::SetSteering,
+    )
+    .size(40)
+    .text_size(40);
+    if !steering.enabled {
+      return toggle.into();
+    }
+
+    column![
+      toggle,
+      row![
+        text(format!("Lock to Lock: {:.0}°", steering.lock_to_lock)).size(20),
+        slider(
+          GyroSteering::LOCK_TO_LOCK_RANGE,
+          steering.lock_to_lock,
+          Message::SetLockToLock
+        )
+        .step(10.0),
+        button(text("Recenter").size(20)).on_press(Message::RecenterSteering),
+      ]
+      .spacing(8)
+      .align_items(Alignment::Center)
+    ]
+    .spacing(8)
+    .into()
+  }
+
+  /// The start button, with an option to use a free port if the last start failed to bind.
+  fn start_buttons(&self) -> Element<'_, Message> {
+    let start = button(
+      text("Start Server")
+        .size(30)
+        .horizontal_alignment(Horizontal::Center)
+        .width(Length::Fill),
+    )
+    .on_press(Message::StartServer)
+    .width(Length::Fill);
+
+    if self.bind_failed {
+      row![
+        start,
+        button(
+          text("Use a Free Port")
+            .size(30)
+            .horizontal_alignment(Horizontal::Center)
+            .width(Length::Fill)
+        )
+        .on_press(Message::StartServerOnFreePort)
+        .width(Length::Fill)
+      ]
+      .spacing(8)
+      .into()
+    } else {
+      start.into()
+    }
+  }
+
+  /// Pick the controller of the first pad, and show the pad of each controller.
+  fn controller_selector(&self) -> Element<'_, Message> {
+    let options = [Selection::Auto]
+      .into_iter()
+      .chain(self.controllers.iter().map(|&h| Selection::Handle(h)))
+      .collect::<Vec<_>>();
+    let status = if self.controllers.is_empty() {
+      "No controller connected".into()
+    } else {
+      self
+        .pads
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| h.map(|h| format!("Pad {}: {}", i + 1, Selection::Handle(h))))
+        .collect::<Vec<_>>()
+        .join(", ")
+    };
+    row![
+      text("Pad 1:").size(20),
+      pick_list(
+        options,
+        Some(self.controller_selection),
+        Message::SelectController
+      )
+      .text_size(20),
+      text(status).size(20),
+    ]
+    .spacing(8)
+    .align_items(Alignment::Center)
+    .into()
+  }
+
+  /// Sticks, triggers, buttons and mouse movement of the latest input snapshot,
+  /// with the update period jitter.
+  fn live_view(&self) -> Element<'_, Message> {
+    if !self.show_live_view {
+      return column![].into();
+    }
+    column![
+      canvas(LiveView {
+        snapshot: &self.snapshot,
+        trail: &self.trail,
+      })
+      .width(Length::Fill)
+      .height(240),
+      text(format!("Period jitter: {}", self.snapshot.jitter)).size(16),
+    ]
+    .spacing(8)
+    .into()
+  }
+
+  /// Connected clients with their roles, and an option to make an observer the controller.
+  fn clients_view(&self) -> Element<'_, Message> {
+    if self.clients.is_empty() {
+      return text("No client connected").size(20).into();
+    }
+    column(
+      self
+        .clients
+        .iter()
+        .map(|client| {
+          let info = text(format!("Client {} ({})", client.id, client.addr)).size(20);
+          let stats = text(self.client_stats(client)).size(20);
+          match client.role {
+            Role::Controller => row![info, text("Controller").size(20), stats],
+            Role::Observer => row![
+              info,
+              text("Observer").size(20),
+              stats,
+              button(text("Make Controller").size(20)).on_press(Message::SetController(client.id))
+            ],
+          }
+          .spacing(8)
+          .align_items(Alignment::Center)
+          .into()
+        })
+        .collect(),
+    )
+    .spacing(8)
+    .into()
+  }
+
+  /// The QR code of the URI for the client to connect to the server, e.g. by scanning it with a phone.
+  fn connect_uri_view(&self) -> Element<'_, Message> {
+    match &self.connect_uri {
+      Some((uri, state)) => row![
+        QRCode::new(state).cell_size(4),
+        text(format!("stickdeck-win.exe {}", uri)).size(20),
+      ]
+      .spacing(16)
+      .align_items(Alignment::Center)
+      .into(),
+      None => column![].into(),
+    }
+  }
+
+  fn api_status(&self) -> api::Status {
+    let config = &self.flags.config;
+    api::Status {
+      started: self.server.is_some(),
+      reverse: self.reverse,
+      address: self.server.as_ref().map(|s| s.local_addr().to_string()),
+      profile: config.active_profile.clone(),
+      profiles: config.profiles.keys().cloned().collect(),
+      input_update_interval_ms: config.profile().input_update_interval_ms,
+      fixed_rate: config.profile().fixed_rate,
+      settings_applied: self.applied_seq == self.sent_seq,
+      controllers: self.controllers.clone(),
+      clients: self.clients.iter().map(Into::into).collect(),
+      error: self.error.clone(),
+      server_error: self.server_error.clone(),
+    }
+  }
+
+  /// Session duration, queue depth and rates of a client.
+  fn client_stats(&self, client: &ClientInfo) -> String {
+    let mut stats = format!(
+      "| {} | queue {}/{}",
+      dashboard::format_duration(client.connected_at.elapsed()),
+      client.queued,
+      server::CLIENT_QUEUE_SIZE
+    );
+    if let Some(rates) = self.dashboard.rates(client.id) {
+      stats.push_str(&format!(
+        " | {:.0} packets/s | {} | write {}us",
+        rates.packets,
+        dashboard::format_bytes_rate(rates.bytes),
+        rates.write_time.as_micros()
+      ));
+    }
+    stats
+  }
+
+  /// The input update rate graph and the last server error.
+  fn dashboard_view(&self) -> Element<'_, Message> {
+    let expected = 1000.0 / self.flags.config.profile().input_update_interval_ms as f64;
+    column![
+      text("Input Updates per Second").size(20),
+      canvas(self.dashboard.graph(expected))
+        .width(Length::Fill)
+        .height(120),
+      text(format!(
+        "Last error: {}",
+        self.server_error.as_deref().unwrap_or("none")
+      ))
+      .size(20),
+    ]
+    .spacing(8)
+    .into()
+  }
+
+  fn profile_selector(&self) -> Element<'_, Message> {
+    row![
+      text("Profile:").size(20),
+      pick_list(
+        self
+          .flags
+          .config
+          .profiles
+          .keys()
+          .cloned()
+          .collect::<Vec<_>>(),
+        Some(self.flags.config.active_profile.clone()),
+        Message::SelectProfile
+      )
+      .text_size(20)
+    ]
+    .spacing(8)
+    .align_items(Alignment::Center)
+    .into()
+  }
+
+  fn save_config(&mut self) {
+    if let Err(e) = self.flags.config.save() {
+      error!("{}", e);
+      self.error = Some(e.to_string());
+    }
+  }
+
+  fn error_text(&self) -> Element<'_, Message> {
+    text(self.error.as_deref().unwrap_or(""))
+      .size(20)
+      .style(Color::from_rgb(0.9, 0.3, 0.3))
+      .into()
+  }
+}
+
+/// Total number of input updates, for the update rate graph.
+fn input_updates() -> u64 {
+  metrics::counter("input updates").get()
+}
+
+/// The URI to connect to the server listening at the address.
+/// Use the main interface address if the server listens on all interfaces.
+/// Return `None` if there is no address to connect to.
+fn connect_uri(addr: SocketAddr) -> Option<(ConnectUri, qr_code::State)> {
+  let ip = match addr.ip() {
+    ip if ip.is_unspecified() => Bind::Auto.ip(),
+    ip => ip,
+  };
+  if ip.is_unspecified() {
+    return None;
+  }
+  let uri = ConnectUri::new(SocketAddr::new(ip, addr.port()).to_string());
+  let state = qr_code::State::new(uri.to_string()).ok()?;
+  Some((uri, state))
+}
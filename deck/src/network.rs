@@ -0,0 +1,115 @@
+use local_ip_address::{list_afinet_netifas, local_ip};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+  fmt,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Where the server listens.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Bind {
+  /// The address of the interface with the default route.
+  #[default]
+  Auto,
+  /// All IPv4 interfaces.
+  AllV4,
+  /// All IPv6 interfaces, and IPv4 interfaces on systems with dual-stack sockets like Linux.
+  AllV6,
+  /// A specific address of an interface.
+  Address(IpAddr),
+}
+
+impl Bind {
+  /// The address to bind to.
+  /// [`Bind::Auto`] falls back to all IPv4 interfaces if there is no network.
+  pub fn ip(&self) -> IpAddr {
+    match self {
+      Bind::Auto => local_ip().unwrap_or_else(|e| {
+        warn!(
+          "Failed to get the local IP address: {}, listening on all interfaces",
+          e
+        );
+        Ipv4Addr::UNSPECIFIED.into()
+      }),
+      Bind::AllV4 => Ipv4Addr::UNSPECIFIED.into(),
+      Bind::AllV6 => Ipv6Addr::UNSPECIFIED.into(),
+      Bind::Address(ip) => *ip,
+    }
+  }
+}
+
+/// An option of the bind address picker.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BindOption {
+  pub bind: Bind,
+  /// The interface name of [`Bind::Address`], if it's available.
+  #[serde(default)]
+  pub interface: Option<String>,
+}
+
+impl fmt::Display for BindOption {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match (&self.bind, &self.interface) {
+      (Bind::Auto, _) => write!(f, "Auto"),
+      (Bind::AllV4, _) => write!(f, "All Interfaces (IPv4)"),
+      (Bind::AllV6, _) => write!(f, "All Interfaces (IPv6)"),
+      (Bind::Address(ip), Some(name)) => write!(f, "{} ({})", name, ip),
+      (Bind::Address(ip), None) => write!(f, "{}", ip),
+    }
+  }
+}
+
+/// The bind options with the addresses of the available interfaces, except loopback.
+/// Return only the generic options if the interfaces can't be listed.
+pub fn bind_options() -> Vec<BindOption> {
+  let interfaces = list_afinet_netifas().unwrap_or_else(|e| {
+    warn!("Failed to list the network interfaces: {}", e);
+    Vec::new()
+  });
+  options(interfaces)
+}
+
+fn options(interfaces: Vec<(String, IpAddr)>) -> Vec<BindOption> {
+  [Bind::Auto, Bind::AllV4, Bind::AllV6]
+    .into_iter()
+    .map(|bind| BindOption {
+      bind,
+      interface: None,
+    })
+    .chain(
+      interfaces
+        .into_iter()
+        .filter(|(_, ip)| !ip.is_loopback())
+        .map(|(name, ip)| BindOption {
+          bind: Bind::Address(ip),
+          interface: Some(name),
+        }),
+    )
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_options() {
+    let wlan = IpAddr::from([192, 168, 1, 2]);
+    let options = options(vec![
+      ("lo".into(), Ipv4Addr::LOCALHOST.into()),
+      ("wlan0".into(), wlan),
+    ]);
+    assert_eq!(
+      options.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+      [
+        "Auto",
+        "All Interfaces (IPv4)",
+        "All Interfaces (IPv6)",
+        "wlan0 (192.168.1.2)"
+      ]
+    );
+    assert_eq!(options[3].bind.ip(), wlan);
+    assert_eq!(options[2].bind.ip(), Ipv6Addr::UNSPECIFIED);
+  }
+}
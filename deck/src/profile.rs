@@ -0,0 +1,480 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{fmt, ops::RangeInclusive};
+
+/// Settings which can be switched as a whole, e.g. for different PCs or games.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+  pub input_update_interval_ms: u64,
+  /// Send the gamepad state every update even if it's not changed,
+  /// for games which expect a steady report rate.
+  pub fixed_rate: bool,
+  /// Apply the smoothing filters. Turn it off to compare with the raw input.
+  pub smoothing: bool,
+  /// Smoothing of the trackpad mouse (`MouseMove`).
+  pub trackpad_filter: FilterConfig,
+  /// Smoothing of the gyro (`GyroMove`).
+  pub gyro_filter: FilterConfig,
+  pub gyro_mode: GyroMode,
+  /// Settings of [`GyroMode::RightStick`].
+  pub gyro_stick: GyroStick,
+  pub flick_stick: FlickStick,
+  pub steering: GyroSteering,
+  pub left_stick: StickOutput,
+  pub right_stick: StickOutput,
+  /// Settings of the digital [`StickOutput`]s.
+  pub stick_digital: StickDigital,
+  /// Speed of the mouse after smoothing.
+  pub sensitivity: Sensitivity,
+}
+
+impl Default for Profile {
+  fn default() -> Self {
+    Self {
+      input_update_interval_ms: 3,
+      fixed_rate: false,
+      smoothing: true,
+      trackpad_filter: FilterConfig::None,
+      gyro_filter: FilterKind::OneEuro.default_config(),
+      gyro_mode: GyroMode::Off,
+      gyro_stick: GyroStick::default(),
+      flick_stick: FlickStick::default(),
+      steering: GyroSteering::default(),
+      left_stick: StickOutput::Stick,
+      right_stick: StickOutput::Stick,
+      stick_digital: StickDigital::default(),
+      sensitivity: Sensitivity::default(),
+    }
+  }
+}
+
+/// Mouse sensitivity, applied to the total movement of the trackpad and the gyro.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Sensitivity {
+  /// Multiplier of both axes.
+  pub multiplier: f32,
+  pub scale_x: f32,
+  pub scale_y: f32,
+  /// The multiplier while `BtnPrecision` is held.
+  pub precision: f32,
+  /// Acceleration multiplies the movement by `1 + (acceleration * (speed - offset)) ^ exponent`,
+  /// up to `max_gain`. The speed is in units per second. `0` turns acceleration off.
+  pub acceleration: f32,
+  pub offset: f32,
+  pub exponent: f32,
+  pub max_gain: f32,
+}
+
+impl Default for Sensitivity {
+  fn default() -> Self {
+    Self {
+      multiplier: 1.0,
+      scale_x: 1.0,
+      scale_y: 1.0,
+      precision: 0.3,
+      acceleration: 0.0,
+      offset: 0.0,
+      exponent: 1.0,
+      max_gain: 4.0,
+    }
+  }
+}
+
+impl Sensitivity {
+  pub const MULTIPLIER_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+
+  /// Clamp the values into their valid ranges. Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let before = *self;
+    let range = Self::MULTIPLIER_RANGE;
+    for m in [&mut self.multiplier, &mut self.scale_x, &mut self.scale_y] {
+      *m = m.clamp(*range.start(), *range.end());
+    }
+    self.precision = self.precision.clamp(0.01, 1.0);
+    self.acceleration = self.acceleration.max(0.0);
+    self.offset = self.offset.max(0.0);
+    self.exponent = self.exponent.clamp(0.1, 4.0);
+    self.max_gain = self.max_gain.max(1.0);
+    if *self != before {
+      warn!("Sensitivity {:?} is out of range, using {:?}", before, self);
+    }
+    *self != before
+  }
+}
+
+/// What the gyro (`GyroMove`) controls.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GyroMode {
+  #[default]
+  Off,
+  /// Move the mouse, together with the trackpad.
+  Mouse,
+  /// Deflect the right stick, for games which don't accept mouse and gamepad at once.
+  RightStick,
+}
+
+impl GyroMode {
+  pub const ALL: [GyroMode; 3] = [GyroMode::Off, GyroMode::Mouse, GyroMode::RightStick];
+}
+
+impl fmt::Display for GyroMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      GyroMode::Off => write!(f, "Off"),
+      GyroMode::Mouse => write!(f, "Mouse"),
+      GyroMode::RightStick => write!(f, "Right Stick"),
+    }
+  }
+}
+
+/// Conversion of gyro movement into right stick deflection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct GyroStick {
+  /// Full deflection per 1000 units per second of gyro movement.
+  pub sensitivity: f32,
+  /// The inner deadzone of the game as a fraction of full deflection.
+  /// Any gyro movement deflects the stick at least this much, so slow movement isn't swallowed.
+  pub deadzone: f32,
+  /// The weight of the physical right stick added to the gyro deflection. `0` ignores the stick.
+  pub blend: f32,
+  /// Convert the trackpad (`MouseMove`) too instead of moving the mouse,
+  /// e.g. if the gyro is bound to `MouseMove` in a custom layout.
+  pub trackpad: bool,
+}
+
+impl Default for GyroStick {
+  fn default() -> Self {
+    Self {
+      sensitivity: 1.0,
+      deadzone: 0.1,
+      blend: 1.0,
+      trackpad: false,
+    }
+  }
+}
+
+impl GyroStick {
+  /// Clamp the values into their valid ranges. Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let before = *self;
+    self.sensitivity = self.sensitivity.clamp(0.01, 100.0);
+    self.deadzone = self.deadzone.clamp(0.0, 0.9);
+    self.blend = self.blend.clamp(0.0, 1.0);
+    if *self != before {
+      warn!("Gyro stick {:?} is out of range, using {:?}", before, self);
+    }
+    *self != before
+  }
+}
+
+/// Flick stick: the right stick turns the camera with the mouse.
+/// Pushing the stick turns to its direction at once, rotating it keeps turning.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct FlickStick {
+  pub enabled: bool,
+  /// Mouse movement of a full turn in the game, see the calibration in the UI.
+  pub pixels_per_360: f32,
+  /// Deflection which starts a flick, as a fraction of full deflection.
+  pub threshold: f32,
+  /// A flick is spread over this time, so it doesn't look like a teleport.
+  pub flick_time_ms: f32,
+}
+
+impl Default for FlickStick {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      pixels_per_360: 3600.0,
+      threshold: 0.9,
+      flick_time_ms: 100.0,
+    }
+  }
+}
+
+impl FlickStick {
+  pub const PIXELS_PER_360_RANGE: RangeInclusive<f32> = 100.0..=20000.0;
+
+  /// Clamp the values into their valid ranges. Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let before = *self;
+    let range = Self::PIXELS_PER_360_RANGE;
+    self.pixels_per_360 = self.pixels_per_360.clamp(*range.start(), *range.end());
+    self.threshold = self.threshold.clamp(0.1, 1.0);
+    self.flick_time_ms = self.flick_time_ms.clamp(0.0, 1000.0);
+    if *self != before {
+      warn!("Flick stick {:?} is out of range, using {:?}", before, self);
+    }
+    *self != before
+  }
+}
+
+/// Gyro steering: tilting the Deck like a wheel moves the left stick horizontally.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct GyroSteering {
+  pub enabled: bool,
+  /// The rotation axis of the wheel.
+  pub axis: SteeringAxis,
+  /// Degrees from full left to full right.
+  pub lock_to_lock: f32,
+  /// How fast the wheel returns to the center, as a fraction per second.
+  /// It keeps the center from drifting, `0` turns it off.
+  pub centering: f32,
+  pub invert: bool,
+}
+
+impl Default for GyroSteering {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      axis: SteeringAxis::Roll,
+      lock_to_lock: 180.0,
+      centering: 0.0,
+      invert: false,
+    }
+  }
+}
+
+impl GyroSteering {
+  pub const LOCK_TO_LOCK_RANGE: RangeInclusive<f32> = 30.0..=900.0;
+
+  /// Clamp the values into their valid ranges. Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let before = *self;
+    let range = Self::LOCK_TO_LOCK_RANGE;
+    self.lock_to_lock = self.lock_to_lock.clamp(*range.start(), *range.end());
+    self.centering = self.centering.clamp(0.0, 10.0);
+    if *self != before {
+      warn!("Steering {:?} is out of range, using {:?}", before, self);
+    }
+    *self != before
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SteeringAxis {
+  /// Around the axis from the bottom to the top of the Deck.
+  Roll,
+  /// Around the axis through the screen, when the Deck is held upright.
+  Yaw,
+}
+
+/// What a stick outputs.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StickOutput {
+  #[default]
+  Stick,
+  /// The D-pad buttons of the gamepad.
+  Dpad,
+  /// The W, A, S and D keys.
+  Wasd,
+  /// The arrow keys.
+  Arrows,
+}
+
+impl StickOutput {
+  pub const ALL: [StickOutput; 4] = [
+    StickOutput::Stick,
+    StickOutput::Dpad,
+    StickOutput::Wasd,
+    StickOutput::Arrows,
+  ];
+}
+
+impl fmt::Display for StickOutput {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      StickOutput::Stick => write!(f, "Stick"),
+      StickOutput::Dpad => write!(f, "D-Pad"),
+      StickOutput::Wasd => write!(f, "WASD"),
+      StickOutput::Arrows => write!(f, "Arrow Keys"),
+    }
+  }
+}
+
+/// Conversion of a stick into 4 or 8 digital directions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct StickDigital {
+  /// Deflection which presses a direction, as a fraction of full deflection.
+  pub threshold: f32,
+  /// Deflection below which the directions are released.
+  /// Lower than `threshold`, so the keys don't chatter around it.
+  pub release: f32,
+  /// Degrees of each diagonal sector, where two directions are pressed.
+  /// `0` is 4-way, `45` is 8-way with even sectors.
+  pub diagonal_angle: f32,
+  /// Degrees the stick must move past a sector border to change the directions.
+  pub angle_hysteresis: f32,
+}
+
+impl Default for StickDigital {
+  fn default() -> Self {
+    Self {
+      threshold: 0.5,
+      release: 0.4,
+      diagonal_angle: 45.0,
+      angle_hysteresis: 5.0,
+    }
+  }
+}
+
+impl StickDigital {
+  /// Clamp the values into their valid ranges. Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let before = *self;
+    self.threshold = self.threshold.clamp(0.05, 1.0);
+    self.release = self.release.clamp(0.0, self.threshold);
+    self.diagonal_angle = self.diagonal_angle.clamp(0.0, 80.0);
+    self.angle_hysteresis = self.angle_hysteresis.clamp(0.0, 20.0);
+    if *self != before {
+      warn!(
+        "Stick digital {:?} is out of range, using {:?}",
+        before, self
+      );
+    }
+    *self != before
+  }
+}
+
+/// A smoothing filter of mouse movement with its parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "kind")]
+pub enum FilterConfig {
+  None,
+  /// Exponential moving average. `alpha` is the weight of the new input in `(0, 1]`.
+  Exponential {
+    alpha: f32,
+  },
+  /// The One-Euro filter, which smooths slow movement more than fast movement.
+  /// `min_cutoff` and `d_cutoff` are in Hz.
+  OneEuro {
+    min_cutoff: f32,
+    beta: f32,
+    d_cutoff: f32,
+  },
+  /// Movement slower than `threshold` (per second) is averaged over `window` updates,
+  /// faster movement is applied directly, with a soft transition from half the threshold.
+  SoftTier {
+    threshold: f32,
+    window: usize,
+  },
+}
+
+impl FilterConfig {
+  /// Max `window` of [`FilterConfig::SoftTier`].
+  pub const MAX_WINDOW: usize = 32;
+
+  pub fn kind(&self) -> FilterKind {
+    match self {
+      FilterConfig::None => FilterKind::None,
+      FilterConfig::Exponential { .. } => FilterKind::Exponential,
+      FilterConfig::OneEuro { .. } => FilterKind::OneEuro,
+      FilterConfig::SoftTier { .. } => FilterKind::SoftTier,
+    }
+  }
+
+  /// Clamp the parameters into their valid ranges. Return `true` if anything is changed.
+  fn validate(&mut self) -> bool {
+    let before = *self;
+    match self {
+      FilterConfig::None => {}
+      FilterConfig::Exponential { alpha } => *alpha = alpha.clamp(0.01, 1.0),
+      FilterConfig::OneEuro {
+        min_cutoff,
+        beta,
+        d_cutoff,
+      } => {
+        *min_cutoff = min_cutoff.max(0.01);
+        *beta = beta.max(0.0);
+        *d_cutoff = d_cutoff.max(0.01);
+      }
+      FilterConfig::SoftTier { threshold, window } => {
+        *threshold = threshold.max(0.0);
+        *window = (*window).clamp(1, Self::MAX_WINDOW);
+      }
+    }
+    if *self != before {
+      warn!("Filter {:?} is out of range, using {:?}", before, self);
+    }
+    *self != before
+  }
+}
+
+/// The kind of a [`FilterConfig`], to pick a filter with the default parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+  None,
+  Exponential,
+  OneEuro,
+  SoftTier,
+}
+
+impl FilterKind {
+  pub const ALL: [FilterKind; 4] = [
+    FilterKind::None,
+    FilterKind::Exponential,
+    FilterKind::OneEuro,
+    FilterKind::SoftTier,
+  ];
+
+  pub fn default_config(&self) -> FilterConfig {
+    match self {
+      FilterKind::None => FilterConfig::None,
+      FilterKind::Exponential => FilterConfig::Exponential { alpha: 0.5 },
+      FilterKind::OneEuro => FilterConfig::OneEuro {
+        min_cutoff: 2.0,
+        beta: 0.01,
+        d_cutoff: 1.0,
+      },
+      FilterKind::SoftTier => FilterConfig::SoftTier {
+        threshold: 500.0,
+        window: 8,
+      },
+    }
+  }
+}
+
+impl fmt::Display for FilterKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      FilterKind::None => write!(f, "None"),
+      FilterKind::Exponential => write!(f, "Exponential"),
+      FilterKind::OneEuro => write!(f, "One-Euro"),
+      FilterKind::SoftTier => write!(f, "Soft Tier"),
+    }
+  }
+}
+
+impl Profile {
+  pub const INPUT_UPDATE_INTERVAL_MS_RANGE: RangeInclusive<u64> = 1..=50;
+
+  /// Clamp values into their valid ranges. Return `true` if anything is changed.
+  pub fn validate(&mut self) -> bool {
+    let mut changed = false;
+    let range = Self::INPUT_UPDATE_INTERVAL_MS_RANGE;
+    let interval = self
+      .input_update_interval_ms
+      .clamp(*range.start(), *range.end());
+    if interval != self.input_update_interval_ms {
+      warn!(
+        "input_update_interval_ms {} is out of range, using {}",
+        self.input_update_interval_ms, interval
+      );
+      self.input_update_interval_ms = interval;
+      changed = true;
+    }
+    changed |= self.trackpad_filter.validate();
+    changed |= self.gyro_filter.validate();
+    changed |= self.gyro_stick.validate();
+    changed |= self.flick_stick.validate();
+    changed |= self.steering.validate();
+    changed |= self.stick_digital.validate();
+    changed |= self.sensitivity.validate();
+    changed
+  }
+}
@@ -1,54 +1,530 @@
 use crate::gamepad::XGamepad;
-use log::info;
+use log::{debug, info, warn};
 use std::{
-  io::{self, Write},
-  net::{TcpListener, TcpStream},
-  sync::mpsc,
+  collections::BTreeMap,
+  io::{self, Read, Write},
+  net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+  sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    mpsc::{self, RecvTimeoutError, TrySendError},
+    Arc, Mutex,
+  },
   thread,
+  time::{Duration, Instant},
 };
-use stickdeck_common::{perf, Packet, PACKET_FRAME_SIZE};
+use stickdeck_common::{counter, perf, Packet, Role, PACKET_FRAME_SIZE};
 
 // Use macro to implement SerializableGamepad trait for production code
 stickdeck_common::impl_serializable_gamepad!(XGamepad);
 
-pub fn spawn(addr: &str, connected_tx: mpsc::Sender<mpsc::SyncSender<Packet<XGamepad>>>) {
-  let listener =
-    TcpListener::bind(addr).unwrap_or_else(|_| panic!("Failed to bind to address {}", addr));
+/// How often the server threads check if the server is stopped.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait before connecting to the client again in reverse mode.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// How many packets can be queued for a client before the sender waits or drops them.
+pub const CLIENT_QUEUE_SIZE: usize = 8;
 
-  info!("Server listening on {}", addr);
+/// A connected client, see [`Server::clients`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+  pub id: u64,
+  pub addr: SocketAddr,
+  pub role: Role,
+  pub connected_at: Instant,
+  pub packets_sent: u64,
+  pub bytes_sent: u64,
+  /// Total time spent writing to the socket.
+  pub write_time: Duration,
+  /// Packets waiting to be sent, up to [`CLIENT_QUEUE_SIZE`].
+  pub queued: usize,
+}
+
+struct Client {
+  id: u64,
+  addr: SocketAddr,
+  connected_at: Instant,
+  /// A handle to the client's socket, to close it when stopping.
+  stream: TcpStream,
+  tx: mpsc::SyncSender<Packet<XGamepad>>,
+  /// Whether the client is the controller. The role is sent to the client when it changes.
+  controller: Arc<AtomicBool>,
+  stats: Arc<ClientStats>,
+}
+
+/// Counters of a client, updated by the threads sending to it.
+#[derive(Default)]
+struct ClientStats {
+  packets: AtomicU64,
+  bytes: AtomicU64,
+  write_us: AtomicU64,
+  queued: AtomicUsize,
+}
+
+impl ClientStats {
+  /// Queue the packet for the client, waiting if the queue is full.
+  /// Return `false` if the client is disconnected.
+  fn send(&self, tx: &mpsc::SyncSender<Packet<XGamepad>>, data: Packet<XGamepad>) -> bool {
+    // count before sending, so the writer never sees a negative depth
+    self.queued.fetch_add(1, Ordering::Relaxed);
+    let ok = tx.send(data).is_ok();
+    if !ok {
+      self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+    ok
+  }
+
+  /// Queue the packet for the client without waiting.
+  fn try_send(
+    &self,
+    tx: &mpsc::SyncSender<Packet<XGamepad>>,
+    data: Packet<XGamepad>,
+  ) -> Result<(), TrySendError<Packet<XGamepad>>> {
+    self.queued.fetch_add(1, Ordering::Relaxed);
+    let res = tx.try_send(data);
+    if res.is_err() {
+      self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+    res
+  }
+}
+
+/// State shared by the server threads.
+#[derive(Default)]
+struct Clients {
+  clients: Vec<Client>,
+  next_id: u64,
+  /// The last state of each pad, sent to new clients.
+  pads: BTreeMap<u8, XGamepad>,
+  /// The last error of any client, see [`Server::last_error`].
+  last_error: Option<String>,
+}
+
+impl Clients {
+  /// Make the client the controller and the others observers.
+  /// Return `false` if the client doesn't exist.
+  fn set_controller(&self, id: u64) -> bool {
+    if !self.clients.iter().any(|c| c.id == id) {
+      return false;
+    }
+    for client in self.clients.iter() {
+      client.controller.store(client.id == id, Ordering::Relaxed);
+    }
+    true
+  }
+
+  /// Remove the client. If it's the controller, the oldest client becomes the controller.
+  fn remove(&mut self, id: u64) {
+    self.clients.retain(|c| c.id != id);
+    if !self
+      .clients
+      .iter()
+      .any(|c| c.controller.load(Ordering::Relaxed))
+    {
+      if let Some(client) = self.clients.first() {
+        client.controller.store(true, Ordering::Relaxed);
+        info!("Client {} is the controller", client.id);
+      }
+    }
+  }
+}
+
+/// A running server, see [`spawn`].
+pub struct Server {
+  addr: SocketAddr,
+  reverse: bool,
+  stopped: Arc<AtomicBool>,
+  clients: Arc<Mutex<Clients>>,
+  handles: Vec<thread::JoinHandle<()>>,
+}
 
-  thread::spawn(move || {
-    // only accept one client because we will consume the receiver
-    let mut stream = listener
-      .incoming()
-      .next()
+impl Server {
+  /// The address the server is listening at, or the client address in reverse mode.
+  pub fn local_addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Whether the server connects to the client, see [`connect`].
+  pub fn is_reverse(&self) -> bool {
+    self.reverse
+  }
+
+  /// Connected clients, oldest first.
+  pub fn clients(&self) -> Vec<ClientInfo> {
+    self
+      .clients
+      .lock()
       .unwrap()
-      .expect("Failed to accept connection");
-    stream.set_nodelay(true).expect("Failed to set nodelay");
-    info!("New client connected");
+      .clients
+      .iter()
+      .map(|c| ClientInfo {
+        id: c.id,
+        addr: c.addr,
+        role: if c.controller.load(Ordering::Relaxed) {
+          Role::Controller
+        } else {
+          Role::Observer
+        },
+        connected_at: c.connected_at,
+        packets_sent: c.stats.packets.load(Ordering::Relaxed),
+        bytes_sent: c.stats.bytes.load(Ordering::Relaxed),
+        write_time: Duration::from_micros(c.stats.write_us.load(Ordering::Relaxed)),
+        queued: c.stats.queued.load(Ordering::Relaxed),
+      })
+      .collect()
+  }
+
+  /// The last connection or socket error, kept after the client is disconnected.
+  pub fn last_error(&self) -> Option<String> {
+    self.clients.lock().unwrap().last_error.clone()
+  }
+
+  /// Hand the controller role to the client. Other clients become observers.
+  pub fn set_controller(&self, id: u64) {
+    if self.clients.lock().unwrap().set_controller(id) {
+      info!("Client {} is the controller", id);
+    }
+  }
 
-    // use a bounded channel to prevent network buffer from growing too large
-    let (data_tx, data_rx) = mpsc::sync_channel(8);
+  /// Close the listener and the client sockets, then wait for the server threads to exit.
+  pub fn stop(self) {
+    self.stopped.store(true, Ordering::Relaxed);
+    for client in self.clients.lock().unwrap().clients.iter() {
+      client.stream.shutdown(Shutdown::Both).ok();
+    }
+    for handle in self.handles {
+      handle.join().ok();
+    }
+    info!("Server stopped");
+  }
+}
 
-    connected_tx
-      .send(data_tx)
-      .expect("Failed to send connected signal");
+/// Bind to the address and serve clients in new threads.
+/// The first client is the controller, later clients are observers.
+/// Return [`Err`] if the address can't be bound, e.g. the port is already in use.
+pub fn spawn(
+  addr: &str,
+  connected_tx: mpsc::Sender<mpsc::SyncSender<Packet<XGamepad>>>,
+) -> io::Result<Server> {
+  let listener = TcpListener::bind(addr)?;
+  // don't block on accepting, so the server can be stopped
+  listener.set_nonblocking(true)?;
+  let addr = listener.local_addr()?;
 
-    let mut buf = [0; PACKET_FRAME_SIZE];
+  info!("Server listening on {}", addr);
+
+  let stopped = Arc::new(AtomicBool::new(false));
+  let clients = Arc::new(Mutex::new(Clients::default()));
+  let dispatcher = spawn_dispatcher(connected_tx, &clients, &stopped);
+
+  let acceptor = thread::spawn({
+    let stopped = stopped.clone();
+    let clients = clients.clone();
+    move || {
+      while let Some((stream, addr)) = accept(&listener, &stopped) {
+        if let Err(e) = add_client(stream, addr, &clients, &stopped) {
+          let e = format!("Failed to set up the client from {}: {}", addr, e);
+          warn!("{}", e);
+          clients.lock().unwrap().last_error = Some(e);
+        }
+      }
+    }
+  });
+
+  Ok(Server {
+    addr,
+    reverse: false,
+    stopped,
+    clients,
+    handles: vec![acceptor, dispatcher],
+  })
+}
+
+/// Reverse mode: connect to a listening client (`stickdeck-win --listen`) in a new thread,
+/// retry until connected and reconnect when disconnected.
+/// Return [`Err`] if the address can't be resolved.
+pub fn connect(
+  addr: &str,
+  connected_tx: mpsc::Sender<mpsc::SyncSender<Packet<XGamepad>>>,
+) -> io::Result<Server> {
+  let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::NotFound,
+      format!("{} can't be resolved", addr),
+    )
+  })?;
+
+  info!("Server connecting to {}", addr);
 
-    while let Ok(data) = data_rx.recv() {
-      data.serialize(&mut buf);
-      if perf!(
-        "net write stream",
-        write_stream(&mut stream, &buf).is_err(),
-        10
-      ) {
-        break;
+  let stopped = Arc::new(AtomicBool::new(false));
+  let clients = Arc::new(Mutex::new(Clients::default()));
+  let dispatcher = spawn_dispatcher(connected_tx, &clients, &stopped);
+
+  let dialer = thread::spawn({
+    let stopped = stopped.clone();
+    let clients = clients.clone();
+    move || {
+      while !stopped.load(Ordering::Relaxed) {
+        let id = match TcpStream::connect_timeout(&addr, RETRY_INTERVAL) {
+          Ok(stream) => add_client(stream, addr, &clients, &stopped),
+          Err(e) => Err(e),
+        };
+        match id {
+          // wait until the client is disconnected, then reconnect
+          Ok(id) => {
+            while !stopped.load(Ordering::Relaxed)
+              && clients.lock().unwrap().clients.iter().any(|c| c.id == id)
+            {
+              thread::sleep(STOP_CHECK_INTERVAL);
+            }
+          }
+          Err(e) => {
+            debug!("Failed to connect to {}: {}, retrying...", addr, e);
+            clients.lock().unwrap().last_error =
+              Some(format!("Failed to connect to {}: {}", addr, e));
+            sleep_until_stopped(RETRY_INTERVAL, &stopped);
+          }
+        }
       }
     }
+  });
+
+  Ok(Server {
+    addr,
+    reverse: true,
+    stopped,
+    clients,
+    handles: vec![dialer, dispatcher],
+  })
+}
+
+/// Send a new data channel to the input thread and dispatch the data to the clients in a new thread.
+fn spawn_dispatcher(
+  connected_tx: mpsc::Sender<mpsc::SyncSender<Packet<XGamepad>>>,
+  clients: &Arc<Mutex<Clients>>,
+  stopped: &Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+  // use a bounded channel to prevent network buffer from growing too large
+  let (data_tx, data_rx) = mpsc::sync_channel(8);
+  connected_tx
+    .send(data_tx)
+    .expect("Failed to send connected signal");
+
+  let stopped = stopped.clone();
+  let clients = clients.clone();
+  thread::spawn(move || dispatch(&data_rx, &clients, &stopped))
+}
+
+/// Sleep for the duration, or until the server is stopped.
+fn sleep_until_stopped(duration: Duration, stopped: &AtomicBool) {
+  let mut slept = Duration::ZERO;
+  while slept < duration && !stopped.load(Ordering::Relaxed) {
+    thread::sleep(STOP_CHECK_INTERVAL);
+    slept += STOP_CHECK_INTERVAL;
+  }
+}
+
+/// Wait for a client until the server is stopped.
+fn accept(listener: &TcpListener, stopped: &AtomicBool) -> Option<(TcpStream, SocketAddr)> {
+  while !stopped.load(Ordering::Relaxed) {
+    match listener.accept() {
+      Ok(accepted) => return Some(accepted),
+      Err(e) => {
+        if e.kind() != io::ErrorKind::WouldBlock {
+          warn!("Failed to accept connection: {}", e);
+        }
+        thread::sleep(STOP_CHECK_INTERVAL);
+      }
+    }
+  }
+  None
+}
+
+/// Register the client and serve it in new threads. Return the client id.
+fn add_client(
+  stream: TcpStream,
+  addr: SocketAddr,
+  clients: &Arc<Mutex<Clients>>,
+  stopped: &Arc<AtomicBool>,
+) -> io::Result<u64> {
+  stream.set_nonblocking(false)?;
+  stream.set_nodelay(true)?;
+  let mut reader = stream.try_clone()?;
+  let handle = stream.try_clone()?;
+
+  let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+  let controller = Arc::new(AtomicBool::new(false));
+  let stats = Arc::new(ClientStats::default());
+
+  let (id, pads) = {
+    let mut clients = clients.lock().unwrap();
+    let id = clients.next_id;
+    clients.next_id += 1;
+    controller.store(clients.clients.is_empty(), Ordering::Relaxed);
+    clients.clients.push(Client {
+      id,
+      addr,
+      connected_at: Instant::now(),
+      stream: handle,
+      tx: tx.clone(),
+      controller: controller.clone(),
+      stats: stats.clone(),
+    });
+    // taken with the client registered, so no update is missed
+    let pads: Vec<_> = clients
+      .pads
+      .iter()
+      .map(|(&pad, gamepad)| Packet::Gamepad(pad, gamepad.clone()))
+      .collect();
+    (id, pads)
+  };
+  info!("Client {} connected from {}", id, addr);
+
+  // echo timestamps so the client can measure the latency.
+  // the reader sees the disconnection first, so it removes the client,
+  // which drops the senders and stops the writer
+  thread::spawn({
+    let clients = clients.clone();
+    let stats = stats.clone();
+    move || {
+      echo(&mut reader, &tx, &stats);
+      drop(tx);
+      clients.lock().unwrap().remove(id);
+      info!("Client {} disconnected", id);
+    }
+  });
 
-    info!("Client disconnected");
+  thread::spawn({
+    let clients = clients.clone();
+    let stopped = stopped.clone();
+    let mut stream = stream;
+    move || {
+      if let Err(e) = serve(&mut stream, &rx, pads, &controller, &stats, &stopped) {
+        let e = format!("Failed to send to client {}: {}", id, e);
+        warn!("{}", e);
+        clients.lock().unwrap().last_error = Some(e);
+      }
+      // stop the reader
+      stream.shutdown(Shutdown::Both).ok();
+    }
   });
+
+  Ok(id)
+}
+
+/// Send the input stream to all clients until the server is stopped.
+fn dispatch(
+  data_rx: &mpsc::Receiver<Packet<XGamepad>>,
+  clients: &Mutex<Clients>,
+  stopped: &AtomicBool,
+) {
+  let mut targets = Vec::new(); // prevent reallocation
+  loop {
+    let data = match data_rx.recv_timeout(STOP_CHECK_INTERVAL) {
+      Ok(data) => data,
+      Err(RecvTimeoutError::Timeout) if !stopped.load(Ordering::Relaxed) => continue,
+      Err(_) => break,
+    };
+
+    {
+      let mut clients = clients.lock().unwrap();
+      match &data {
+        Packet::Gamepad(pad, gamepad) => {
+          clients.pads.insert(*pad, gamepad.clone());
+        }
+        Packet::PadRemoved(pad) => {
+          clients.pads.remove(pad);
+        }
+        _ => {}
+      }
+      targets.extend(clients.clients.iter().map(|c| {
+        (
+          c.id,
+          c.tx.clone(),
+          c.stats.clone(),
+          c.controller.load(Ordering::Relaxed),
+        )
+      }));
+    }
+
+    for (id, tx, stats, controller) in targets.drain(..) {
+      if controller {
+        // the controller applies back pressure to the input thread
+        stats.send(&tx, data.clone());
+      } else if let Err(TrySendError::Full(_)) = stats.try_send(&tx, data.clone()) {
+        // a slow observer shouldn't block the controller
+        debug!("Client {} is too slow, dropping {:?}", id, data);
+        counter!("packets dropped").add(1);
+      }
+    }
+  }
+}
+
+/// Write the role and the pad states, then the input stream to the client,
+/// until the server is stopped or the client is disconnected.
+fn serve(
+  stream: &mut TcpStream,
+  rx: &mpsc::Receiver<Packet<XGamepad>>,
+  pads: Vec<Packet<XGamepad>>,
+  controller: &AtomicBool,
+  stats: &ClientStats,
+  stopped: &AtomicBool,
+) -> io::Result<()> {
+  let mut buf = [0; PACKET_FRAME_SIZE];
+  let mut role = None;
+  let mut pads = pads.into_iter();
+
+  loop {
+    // send the role before any input and when it's changed
+    let current = if controller.load(Ordering::Relaxed) {
+      Role::Controller
+    } else {
+      Role::Observer
+    };
+    if role != Some(current) {
+      role = Some(current);
+      Packet::<XGamepad>::Role(current).serialize(&mut buf);
+      write_stream(stream, &buf)?;
+    }
+
+    let data = match pads.next() {
+      Some(data) => data,
+      None => match rx.recv_timeout(STOP_CHECK_INTERVAL) {
+        Ok(data) => {
+          stats.queued.fetch_sub(1, Ordering::Relaxed);
+          data
+        }
+        Err(RecvTimeoutError::Timeout) if !stopped.load(Ordering::Relaxed) => continue,
+        Err(_) => return Ok(()),
+      },
+    };
+    perf!("serialize", data.serialize(&mut buf), 1);
+    let start = Instant::now();
+    perf!("net write stream", write_stream(stream, &buf), 10)?;
+    stats
+      .write_us
+      .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    stats.packets.fetch_add(1, Ordering::Relaxed);
+    stats
+      .bytes
+      .fetch_add(PACKET_FRAME_SIZE as u64, Ordering::Relaxed);
+    counter!("packets sent").add(1);
+    counter!("bytes sent").add(PACKET_FRAME_SIZE as u64);
+  }
+}
+
+/// Send timestamps from the client back to the client, until the client socket is closed.
+fn echo(stream: &mut TcpStream, data_tx: &mpsc::SyncSender<Packet<XGamepad>>, stats: &ClientStats) {
+  let mut buf = [0; PACKET_FRAME_SIZE];
+  while stream.read_exact(&mut buf).is_ok() {
+    if buf[0] != 0 {
+      warn!("Unexpected packet from the client: {:?}", buf);
+      continue;
+    }
+    let timestamp = u64::from_le_bytes(buf[1..9].try_into().unwrap());
+    if !stats.send(data_tx, Packet::Timestamp(timestamp)) {
+      break;
+    }
+  }
 }
 
 fn write_stream(stream: &mut TcpStream, buf: &[u8; PACKET_FRAME_SIZE]) -> io::Result<()> {
@@ -69,14 +545,30 @@ impl<Gamepad: SerializableGamepad> SerializablePacket for Packet<Gamepad> {
         buf[0] = 0;
         buf[1..9].copy_from_slice(&timestamp.to_le_bytes());
       }
-      Packet::Gamepad(gamepad) => {
+      Packet::Gamepad(pad, gamepad) => {
         buf[0] = 1;
-        gamepad.serialize(&mut buf[1..]);
+        buf[1] = *pad;
+        gamepad.serialize(&mut buf[2..]);
       }
       Packet::Mouse(mouse) => {
         buf[0] = 2;
         mouse.serialize(&mut buf[1..]);
       }
+      Packet::PadRemoved(pad) => {
+        buf[0] = 3;
+        buf[1] = *pad;
+      }
+      Packet::Role(role) => {
+        buf[0] = 4;
+        buf[1] = match role {
+          Role::Controller => 0,
+          Role::Observer => 1,
+        };
+      }
+      Packet::Keys(keys) => {
+        buf[0] = 5;
+        buf[1] = keys.0;
+      }
     }
   }
 }
@@ -85,7 +577,151 @@ impl<Gamepad: SerializableGamepad> SerializablePacket for Packet<Gamepad> {
 mod tests {
   use super::*;
   use crate::gamepad::XButtons;
+  use stickdeck_common::Keys;
 
   stickdeck_common::impl_deserializable_gamepad!(XGamepad, XButtons);
   stickdeck_common::impl_test_serialize_deserialize!(XGamepad, XButtons);
+
+  #[test]
+  fn test_serialize_pad_index() {
+    let gamepad = XGamepad {
+      thumb_ry: -0x1234,
+      ..Default::default()
+    };
+    let mut buf = [0; PACKET_FRAME_SIZE];
+    Packet::Gamepad(2, gamepad.clone()).serialize(&mut buf);
+    assert_eq!(buf[..2], [1, 2]);
+    assert_eq!(XGamepad::deserialize(&buf[2..]), gamepad);
+
+    let mut buf = [0; PACKET_FRAME_SIZE];
+    Packet::<XGamepad>::PadRemoved(3).serialize(&mut buf);
+    assert_eq!(buf[..2], [3, 3]);
+
+    let mut buf = [0; PACKET_FRAME_SIZE];
+    Packet::<XGamepad>::Keys(Keys(Keys::W | Keys::D)).serialize(&mut buf);
+    assert_eq!(buf[..2], [5, 9]);
+  }
+
+  /// Wait until the stats of the first client count the packets.
+  /// The stats are updated after each write, so they may lag behind what the client has read.
+  fn wait_for_packets(server: &Server, packets: u64) -> ClientInfo {
+    let start = Instant::now();
+    loop {
+      let info = server.clients().remove(0);
+      if info.packets_sent == packets || start.elapsed() > Duration::from_secs(5) {
+        assert_eq!(info.packets_sent, packets);
+        return info;
+      }
+      thread::sleep(Duration::from_millis(1));
+    }
+  }
+
+  fn connect(addr: SocketAddr) -> TcpStream {
+    let client = TcpStream::connect(addr).unwrap();
+    client
+      .set_read_timeout(Some(Duration::from_secs(5)))
+      .unwrap();
+    client
+  }
+
+  fn read(client: &mut TcpStream) -> [u8; PACKET_FRAME_SIZE] {
+    let mut buf = [0; PACKET_FRAME_SIZE];
+    client.read_exact(&mut buf).unwrap();
+    buf
+  }
+
+  #[test]
+  fn test_stop_and_restart() {
+    let (connected_tx, connected_rx) = mpsc::channel();
+    let server = spawn("127.0.0.1:0", connected_tx.clone()).unwrap();
+    let addr = server.local_addr();
+    let data_tx = connected_rx.recv().unwrap();
+
+    // the port is in use
+    assert!(spawn(&addr.to_string(), connected_tx.clone()).is_err());
+
+    let mut client = connect(addr);
+    // the first client is the controller
+    assert_eq!(read(&mut client)[..2], [4, 0]);
+    data_tx.send(Packet::Timestamp(1)).unwrap();
+    assert_eq!(read(&mut client)[0], 0);
+
+    // timestamps from the client are echoed
+    let mut ping = [0; PACKET_FRAME_SIZE];
+    ping[1..9].copy_from_slice(&42u64.to_le_bytes());
+    client.write_all(&ping).unwrap();
+    assert_eq!(read(&mut client), ping);
+
+    // the timestamp and the echo are counted, the role is not
+    let info = wait_for_packets(&server, 2);
+    assert_eq!(info.bytes_sent, 2 * PACKET_FRAME_SIZE as u64);
+    assert_eq!(info.queued, 0);
+
+    // the client socket is closed
+    server.stop();
+    let mut buf = [0; PACKET_FRAME_SIZE];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    assert!(data_tx.send(Packet::Timestamp(2)).is_err());
+
+    // restart on the same port, stop without any client
+    spawn(&addr.to_string(), connected_tx).unwrap().stop();
+  }
+
+  #[test]
+  fn test_roles() {
+    let (connected_tx, connected_rx) = mpsc::channel();
+    let server = spawn("127.0.0.1:0", connected_tx).unwrap();
+    let addr = server.local_addr();
+    let data_tx = connected_rx.recv().unwrap();
+
+    let mut a = connect(addr);
+    assert_eq!(read(&mut a)[..2], [4, 0]);
+    let mut b = connect(addr);
+    assert_eq!(read(&mut b)[..2], [4, 1]);
+
+    // both clients receive the stream
+    data_tx
+      .send(Packet::Gamepad(0, XGamepad::default()))
+      .unwrap();
+    assert_eq!(read(&mut a)[..2], [1, 0]);
+    assert_eq!(read(&mut b)[..2], [1, 0]);
+
+    // hand the controller role to b
+    let clients = server.clients();
+    assert_eq!(clients.len(), 2);
+    assert_eq!(clients[0].role, Role::Controller);
+    server.set_controller(clients[1].id);
+    assert_eq!(read(&mut a)[..2], [4, 1]);
+    assert_eq!(read(&mut b)[..2], [4, 0]);
+
+    // new clients receive the pad states
+    let mut c = connect(addr);
+    assert_eq!(read(&mut c)[..2], [4, 1]);
+    assert_eq!(read(&mut c)[..2], [1, 0]);
+
+    // the oldest client becomes the controller when the controller is disconnected
+    drop(b);
+    assert_eq!(read(&mut a)[..2], [4, 0]);
+    assert_eq!(server.clients().len(), 2);
+
+    server.stop();
+  }
+
+  #[test]
+  fn test_reverse() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (connected_tx, _connected_rx) = mpsc::channel();
+    let server = super::connect(&listener.local_addr().unwrap().to_string(), connected_tx).unwrap();
+    assert!(server.is_reverse());
+
+    let (mut client, _) = listener.accept().unwrap();
+    assert_eq!(read(&mut client)[..2], [4, 0]);
+
+    // reconnect when disconnected
+    drop(client);
+    let (mut client, _) = listener.accept().unwrap();
+    assert_eq!(read(&mut client)[..2], [4, 0]);
+
+    server.stop();
+  }
 }
@@ -0,0 +1,492 @@
+//! Install the StickDeck action set and the default layout into the Steam client,
+//! see `stickdeck setup --help`.
+
+use crate::{
+  binding,
+  vdf::{self, Value},
+};
+use std::{
+  fmt, fs, io,
+  path::{Path, PathBuf},
+};
+
+/// The action set shipped with this version.
+pub const ACTIONS_VDF: &str = include_str!("../stickdeck.vdf");
+
+/// The Steam App ID used when none is configured ("Spacewar").
+pub const DEFAULT_APP_ID: u32 = 480;
+
+/// The file read by the Steam API to find the App ID when the app is not launched by Steam.
+pub const APP_ID_FILENAME: &str = "steam_appid.txt";
+
+/// Read the App ID from [`APP_ID_FILENAME`] in the directory.
+pub fn read_app_id(app_dir: &Path) -> Option<u32> {
+  fs::read_to_string(app_dir.join(APP_ID_FILENAME))
+    .ok()
+    .and_then(|content| content.trim().parse().ok())
+}
+
+/// Steam root candidates relative to the home directory, in order of preference.
+const STEAM_ROOT_CANDIDATES: &[&str] = &[
+  ".local/share/Steam",
+  ".steam/steam",
+  ".steam/root",
+  // Flatpak
+  ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+  ".var/app/com.valvesoftware.Steam/data/Steam",
+];
+
+/// Find the Steam installation under the home directory.
+pub fn find_steam_root(home: &Path) -> Option<PathBuf> {
+  STEAM_ROOT_CANDIDATES
+    .iter()
+    .map(|candidate| home.join(candidate))
+    .find(|root| root.join("steamapps").is_dir())
+}
+
+/// The installed action set file for the app.
+pub fn actions_path(steam_root: &Path, app_id: u32) -> PathBuf {
+  steam_root
+    .join("controller_config")
+    .join(format!("game_actions_{}.vdf", app_id))
+}
+
+/// The default layout file for each Steam account which has logged in on this device.
+pub fn layout_paths(steam_root: &Path, app_id: u32) -> io::Result<Vec<PathBuf>> {
+  let userdata = match fs::read_dir(steam_root.join("userdata")) {
+    Ok(userdata) => userdata,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(e),
+  };
+
+  let mut account_ids = Vec::new();
+  for entry in userdata {
+    let name = entry?.file_name();
+    // skip `0` and anything else which is not an account id
+    if let Some(id) = name.to_str().and_then(|n| n.parse::<u32>().ok()) {
+      if id != 0 {
+        account_ids.push(id);
+      }
+    }
+  }
+  account_ids.sort_unstable();
+
+  Ok(
+    account_ids
+      .into_iter()
+      .map(|id| {
+        steam_root
+          .join("steamapps/common/Steam Controller Configs")
+          .join(id.to_string())
+          .join("config")
+          .join(app_id.to_string())
+          .join("controller_neptune.vdf")
+      })
+      .collect(),
+  )
+}
+
+/// The state of an existing action set file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Installed {
+  Missing,
+  /// Same as [`ACTIONS_VDF`].
+  Current,
+  /// A StickDeck action set from an older version.
+  Outdated(&'static str),
+  /// Not a StickDeck action set, or not parsable.
+  Foreign,
+}
+
+impl Installed {
+  fn is_stickdeck(&self) -> bool {
+    matches!(self, Installed::Current | Installed::Outdated(_))
+  }
+}
+
+/// Return the action names of the `XBoxControls` action set in an action set file.
+pub fn action_names(root: &Value) -> Option<Vec<&str>> {
+  let set = root
+    .get("In Game Actions")?
+    .get("actions")?
+    .get("XBoxControls")?;
+  Some(
+    set
+      .entries()
+      .filter(|(_, v)| matches!(v, Value::Obj(_)))
+      .flat_map(|(_, category)| category.entries().map(|(name, _)| name))
+      .collect(),
+  )
+}
+
+/// Detect which StickDeck version (if any) the content belongs to.
+pub fn detect(content: &str) -> Installed {
+  if content == ACTIONS_VDF {
+    return Installed::Current;
+  }
+  let Ok(root) = vdf::parse(content) else {
+    return Installed::Foreign;
+  };
+  let Some(names) = action_names(&root) else {
+    return Installed::Foreign;
+  };
+
+  if names.contains(&"LeftMouse") || names.contains(&"RightMouse") {
+    return Installed::Outdated("pre-0.3 layout with LeftMouse/RightMouse");
+  }
+
+  let current = vdf::parse(ACTIONS_VDF).expect("bundled action set should be valid");
+  let mut current = action_names(&current).expect("bundled action set should have XBoxControls");
+  let mut names = names;
+  current.sort_unstable();
+  names.sort_unstable();
+  if names == current {
+    // same actions, only formatting or localization differs
+    Installed::Current
+  } else {
+    Installed::Outdated("action set differs from this version")
+  }
+}
+
+/// One step of the setup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+  CreateDir(PathBuf),
+  Backup {
+    from: PathBuf,
+    to: PathBuf,
+  },
+  Write {
+    path: PathBuf,
+    content: String,
+    reason: String,
+  },
+  Remove(PathBuf),
+  /// Leave an existing file untouched.
+  Keep {
+    path: PathBuf,
+    reason: &'static str,
+  },
+}
+
+impl fmt::Display for Step {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Step::CreateDir(path) => write!(f, "create directory {}", path.display()),
+      Step::Backup { from, to } => write!(f, "back up {} to {}", from.display(), to.display()),
+      Step::Write { path, reason, .. } => write!(f, "write {} ({})", path.display(), reason),
+      Step::Remove(path) => write!(f, "remove {}", path.display()),
+      Step::Keep { path, reason } => write!(f, "keep {} ({})", path.display(), reason),
+    }
+  }
+}
+
+impl Step {
+  pub fn apply(&self) -> io::Result<()> {
+    match self {
+      Step::CreateDir(path) => fs::create_dir_all(path),
+      Step::Backup { from, to } => fs::copy(from, to).map(|_| ()),
+      Step::Write { path, content, .. } => fs::write(path, content),
+      Step::Remove(path) => fs::remove_file(path),
+      Step::Keep { .. } => Ok(()),
+    }
+  }
+}
+
+/// Return a path next to `path` which doesn't exist yet, for backups.
+fn backup_path(path: &Path) -> PathBuf {
+  let mut n = 0;
+  loop {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(if n == 0 {
+      ".bak".to_string()
+    } else {
+      format!(".bak.{}", n)
+    });
+    let candidate = path.with_file_name(name);
+    if !candidate.exists() {
+      return candidate;
+    }
+    n += 1;
+  }
+}
+
+fn read_installed(path: &Path) -> io::Result<Installed> {
+  match fs::read_to_string(path) {
+    Ok(content) => Ok(detect(&content)),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Installed::Missing),
+    // e.g. invalid UTF-8
+    Err(e) if e.kind() == io::ErrorKind::InvalidData => Ok(Installed::Foreign),
+    Err(e) => Err(e),
+  }
+}
+
+/// Push the steps to write `content` to `path`, creating the parent directory if needed.
+fn plan_write(steps: &mut Vec<Step>, path: PathBuf, content: String, reason: String) {
+  let dir = path.parent().expect("path should have a parent");
+  if !dir.is_dir() {
+    steps.push(Step::CreateDir(dir.to_path_buf()));
+  }
+  steps.push(Step::Write {
+    path,
+    content,
+    reason,
+  });
+}
+
+/// Plan the steps to install the action set and the default layout into the Steam root,
+/// and to make the app in `app_dir` use the App ID.
+pub fn plan_install(steam_root: &Path, app_dir: &Path, app_id: u32) -> io::Result<Vec<Step>> {
+  let mut steps = Vec::new();
+
+  if read_app_id(app_dir) != Some(app_id) {
+    plan_write(
+      &mut steps,
+      app_dir.join(APP_ID_FILENAME),
+      app_id.to_string(),
+      format!("use App ID {}", app_id),
+    );
+  }
+
+  let path = actions_path(steam_root, app_id);
+  let reason = match read_installed(&path)? {
+    Installed::Current => None,
+    Installed::Missing => Some("new install".to_string()),
+    Installed::Outdated(detail) => Some(format!("upgrade: {}", detail)),
+    Installed::Foreign => Some("replace a non-StickDeck action set".to_string()),
+  };
+  if let Some(reason) = reason {
+    if path.exists() {
+      steps.push(Step::Backup {
+        from: path.clone(),
+        to: backup_path(&path),
+      });
+    }
+    plan_write(&mut steps, path, ACTIONS_VDF.to_string(), reason);
+  }
+
+  let layout = binding::generate();
+  for path in layout_paths(steam_root, app_id)? {
+    match fs::read_to_string(&path) {
+      Ok(content) if content == layout => {}
+      Ok(content) if binding::is_generated(&content) => plan_write(
+        &mut steps,
+        path,
+        layout.clone(),
+        "update default layout".into(),
+      ),
+      // the user has customized the layout, don't override it
+      Ok(_) => steps.push(Step::Keep {
+        path,
+        reason: "customized layout",
+      }),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => {
+        plan_write(&mut steps, path, layout.clone(), "default layout".into())
+      }
+      Err(e) => return Err(e),
+    }
+  }
+
+  Ok(steps)
+}
+
+/// Plan the steps to remove the action set and the default layout from the Steam root.
+/// Files which are not installed by StickDeck are left untouched.
+pub fn plan_uninstall(steam_root: &Path, app_id: u32) -> io::Result<Vec<Step>> {
+  let mut steps = Vec::new();
+
+  let path = actions_path(steam_root, app_id);
+  if read_installed(&path)?.is_stickdeck() {
+    steps.push(Step::Remove(path));
+  }
+
+  for path in layout_paths(steam_root, app_id)? {
+    if fs::read_to_string(&path).is_ok_and(|content| binding::is_generated(&content)) {
+      steps.push(Step::Remove(path));
+    }
+  }
+
+  Ok(steps)
+}
+
+/// Run the setup against the home directory and the app directory for the App ID, and print each step.
+/// If `dry_run` is `true`, only print the steps.
+pub fn run(
+  home: &Path,
+  app_dir: &Path,
+  app_id: u32,
+  uninstall: bool,
+  dry_run: bool,
+) -> io::Result<()> {
+  let steam_root = find_steam_root(home).ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::NotFound,
+      format!("Steam installation not found in {}", home.display()),
+    )
+  })?;
+  println!("Steam root: {}", steam_root.display());
+  println!("App ID: {}", app_id);
+
+  let steps = if uninstall {
+    plan_uninstall(&steam_root, app_id)?
+  } else {
+    plan_install(&steam_root, app_dir, app_id)?
+  };
+
+  if steps.is_empty() {
+    println!("Nothing to do.");
+  }
+  for step in steps {
+    if dry_run {
+      println!("[dry run] {}", step);
+    } else {
+      println!("{}", step);
+      step.apply()?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{env, process};
+
+  const OLD_ACTIONS_VDF: &str = r##"
+    "In Game Actions"
+    {
+      "actions"
+      {
+        "XBoxControls"
+        {
+          "title" "#Set_XBoxControls"
+          "Button" { "BtnA" "#Action_BtnA" }
+          "StickPadGyro"
+          {
+            "LeftMouse" { "title" "#Action_LeftMouse" "input_mode" "absolute_mouse" }
+            "RightMouse" { "title" "#Action_RightMouse" "input_mode" "absolute_mouse" }
+          }
+        }
+      }
+    }
+  "##;
+
+  /// Create an empty home directory with a Steam installation at `steam_root`.
+  fn home_with_steam(name: &str, steam_root: &str) -> PathBuf {
+    let home = env::temp_dir().join(format!("stickdeck-setup-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&home);
+    fs::create_dir_all(home.join(steam_root).join("steamapps")).unwrap();
+    home
+  }
+
+  fn apply_all(steps: &[Step]) {
+    steps.iter().for_each(|s| s.apply().unwrap());
+  }
+
+  #[test]
+  fn test_find_steam_root() {
+    let home = home_with_steam("flatpak", STEAM_ROOT_CANDIDATES[3]);
+    assert_eq!(
+      find_steam_root(&home),
+      Some(home.join(STEAM_ROOT_CANDIDATES[3]))
+    );
+    assert_eq!(find_steam_root(&home.join("nowhere")), None);
+  }
+
+  #[test]
+  fn test_detect() {
+    assert_eq!(detect(ACTIONS_VDF), Installed::Current);
+    assert!(matches!(detect(OLD_ACTIONS_VDF), Installed::Outdated(_)));
+    assert_eq!(detect("\"In Game Actions\" { }"), Installed::Foreign);
+    assert_eq!(detect("not { a vdf"), Installed::Foreign);
+  }
+
+  #[test]
+  fn test_install_upgrade_uninstall() {
+    let home = home_with_steam("install", STEAM_ROOT_CANDIDATES[0]);
+    let root = find_steam_root(&home).unwrap();
+    let path = actions_path(&root, DEFAULT_APP_ID);
+
+    // fresh install
+    let steps = plan_install(&root, &home, DEFAULT_APP_ID).unwrap();
+    assert!(matches!(steps[1], Step::CreateDir(_)));
+    apply_all(&steps);
+    assert_eq!(read_app_id(&home), Some(DEFAULT_APP_ID));
+    assert_eq!(fs::read_to_string(&path).unwrap(), ACTIONS_VDF);
+    assert!(plan_install(&root, &home, DEFAULT_APP_ID)
+      .unwrap()
+      .is_empty());
+
+    // upgrade an old version with a backup
+    fs::write(&path, OLD_ACTIONS_VDF).unwrap();
+    let steps = plan_install(&root, &home, DEFAULT_APP_ID).unwrap();
+    assert_eq!(steps.len(), 2);
+    apply_all(&steps);
+    assert_eq!(fs::read_to_string(&path).unwrap(), ACTIONS_VDF);
+    let backup = path.with_file_name(format!("game_actions_{}.vdf.bak", DEFAULT_APP_ID));
+    assert_eq!(fs::read_to_string(&backup).unwrap(), OLD_ACTIONS_VDF);
+    assert_ne!(backup_path(&path), backup);
+
+    // default layout for each account, but keep customized ones
+    fs::create_dir_all(root.join("userdata/0")).unwrap();
+    fs::create_dir_all(root.join("userdata/1234")).unwrap();
+    fs::create_dir_all(root.join("userdata/5678")).unwrap();
+    let layouts = layout_paths(&root, DEFAULT_APP_ID).unwrap();
+    assert_eq!(layouts.len(), 2);
+    fs::create_dir_all(layouts[1].parent().unwrap()).unwrap();
+    fs::write(
+      &layouts[1],
+      "\"controller_mappings\" { \"title\" \"Mine\" }",
+    )
+    .unwrap();
+    let steps = plan_install(&root, &home, DEFAULT_APP_ID).unwrap();
+    assert!(matches!(steps.last(), Some(Step::Keep { .. })));
+    apply_all(&steps);
+    assert_eq!(
+      fs::read_to_string(&layouts[0]).unwrap(),
+      binding::generate()
+    );
+    assert!(plan_install(&root, &home, DEFAULT_APP_ID)
+      .unwrap()
+      .iter()
+      .all(|s| matches!(s, Step::Keep { .. })));
+
+    // uninstall
+    apply_all(&plan_uninstall(&root, DEFAULT_APP_ID).unwrap());
+    assert!(!layouts[0].exists());
+    assert!(layouts[1].exists());
+    assert!(!path.exists());
+    assert!(backup.exists());
+
+    // never remove foreign files
+    fs::write(&path, "\"In Game Actions\" { }").unwrap();
+    assert!(plan_uninstall(&root, DEFAULT_APP_ID).unwrap().is_empty());
+
+    fs::remove_dir_all(&home).unwrap();
+  }
+
+  #[test]
+  fn test_custom_app_id() {
+    let home = home_with_steam("app-id", STEAM_ROOT_CANDIDATES[0]);
+    let root = find_steam_root(&home).unwrap();
+    fs::write(home.join(APP_ID_FILENAME), "480\n").unwrap();
+    assert_eq!(read_app_id(&home), Some(DEFAULT_APP_ID));
+
+    run(&home, &home, 1234, false, false).unwrap();
+    assert_eq!(read_app_id(&home), Some(1234));
+    assert!(actions_path(&root, 1234).exists());
+    assert!(!actions_path(&root, DEFAULT_APP_ID).exists());
+    fs::remove_dir_all(&home).unwrap();
+  }
+
+  #[test]
+  fn test_dry_run() {
+    let home = home_with_steam("dry-run", STEAM_ROOT_CANDIDATES[0]);
+    let root = find_steam_root(&home).unwrap();
+    run(&home, &home, DEFAULT_APP_ID, false, true).unwrap();
+    assert!(!actions_path(&root, DEFAULT_APP_ID).exists());
+    assert!(!home.join(APP_ID_FILENAME).exists());
+    assert!(run(&home.join("nowhere"), &home, DEFAULT_APP_ID, false, true).is_err());
+    fs::remove_dir_all(&home).unwrap();
+  }
+}
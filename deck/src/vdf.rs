@@ -0,0 +1,254 @@
+//! A minimal parser and writer for Valve's KeyValues text format (`.vdf`).
+//! Only what we need to inspect Steam Input files is supported:
+//! quoted/unquoted strings, nested objects, `//` comments and `[$...]` conditionals (ignored).
+
+use std::fmt;
+
+/// A value in a KeyValues document.
+/// Keys may repeat (e.g. multiple `"group"` entries), so objects keep their entries in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+  Str(String),
+  Obj(Vec<(String, Value)>),
+}
+
+impl Value {
+  /// Return the first value with the given key (case-insensitive) if this is an object.
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    self
+      .entries()
+      .find(|(k, _)| k.eq_ignore_ascii_case(key))
+      .map(|(_, v)| v)
+  }
+
+  /// Iterate over the entries if this is an object, otherwise yield nothing.
+  pub fn entries(&self) -> impl Iterator<Item = (&str, &Value)> {
+    let entries: &[(String, Value)] = match self {
+      Value::Obj(entries) => entries,
+      Value::Str(_) => &[],
+    };
+    entries.iter().map(|(k, v)| (k.as_str(), v))
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::Str(s) => Some(s),
+      Value::Obj(_) => None,
+    }
+  }
+}
+
+/// Serialize an object's entries as a KeyValues document, indented with tabs like Steam does.
+pub fn to_string(root: &Value) -> String {
+  let mut out = String::new();
+  write_entries(&mut out, root, 0);
+  out
+}
+
+fn write_entries(out: &mut String, value: &Value, depth: usize) {
+  for (key, value) in value.entries() {
+    out.push_str(&"\t".repeat(depth));
+    write_str(out, key);
+    match value {
+      Value::Str(s) => {
+        out.push_str("\t\t");
+        write_str(out, s);
+        out.push('\n');
+      }
+      Value::Obj(_) => {
+        out.push('\n');
+        out.push_str(&"\t".repeat(depth));
+        out.push_str("{\n");
+        write_entries(out, value, depth + 1);
+        out.push_str(&"\t".repeat(depth));
+        out.push_str("}\n");
+      }
+    }
+  }
+}
+
+fn write_str(out: &mut String, s: &str) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' | '\\' => {
+        out.push('\\');
+        out.push(c);
+      }
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+  /// 1-based line number where the error occurred.
+  pub line: usize,
+  pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "VDF parse error at line {}: {}", self.line, self.message)
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+  Str(String),
+  Open,
+  Close,
+}
+
+struct Lexer<'a> {
+  chars: std::iter::Peekable<std::str::Chars<'a>>,
+  line: usize,
+}
+
+impl<'a> Lexer<'a> {
+  fn err(&self, message: &'static str) -> ParseError {
+    ParseError {
+      line: self.line,
+      message,
+    }
+  }
+
+  fn next_token(&mut self) -> Result<Option<Token>, ParseError> {
+    loop {
+      let Some(c) = self.chars.next() else {
+        return Ok(None);
+      };
+      match c {
+        '\n' => self.line += 1,
+        c if c.is_whitespace() => {}
+        '{' => return Ok(Some(Token::Open)),
+        '}' => return Ok(Some(Token::Close)),
+        '/' if self.chars.peek() == Some(&'/') => {
+          // comment until the end of the line
+          for c in self.chars.by_ref() {
+            if c == '\n' {
+              self.line += 1;
+              break;
+            }
+          }
+        }
+        '[' => {
+          // conditional like `[$WIN32]`, not supported so just skip it
+          for c in self.chars.by_ref() {
+            if c == ']' {
+              break;
+            }
+          }
+        }
+        '"' => {
+          let mut s = String::new();
+          loop {
+            match self.chars.next() {
+              None => return Err(self.err("unterminated string")),
+              Some('"') => break,
+              Some('\\') => match self.chars.next() {
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                Some(c) => s.push(c),
+                None => return Err(self.err("unterminated string")),
+              },
+              Some(c) => {
+                if c == '\n' {
+                  self.line += 1;
+                }
+                s.push(c)
+              }
+            }
+          }
+          return Ok(Some(Token::Str(s)));
+        }
+        c => {
+          let mut s = c.to_string();
+          while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+              break;
+            }
+            s.push(c);
+            self.chars.next();
+          }
+          return Ok(Some(Token::Str(s)));
+        }
+      }
+    }
+  }
+}
+
+/// Parse a KeyValues document. The returned value is always an object holding the root entries.
+pub fn parse(content: &str) -> Result<Value, ParseError> {
+  let mut lexer = Lexer {
+    chars: content.chars().peekable(),
+    line: 1,
+  };
+  let entries = parse_entries(&mut lexer, false)?;
+  Ok(Value::Obj(entries))
+}
+
+fn parse_entries(lexer: &mut Lexer, nested: bool) -> Result<Vec<(String, Value)>, ParseError> {
+  let mut entries = Vec::new();
+  loop {
+    let key = match lexer.next_token()? {
+      Some(Token::Str(key)) => key,
+      Some(Token::Close) if nested => return Ok(entries),
+      Some(Token::Close) => return Err(lexer.err("unexpected '}'")),
+      Some(Token::Open) => return Err(lexer.err("unexpected '{'")),
+      None if nested => return Err(lexer.err("missing '}'")),
+      None => return Ok(entries),
+    };
+    let value = match lexer.next_token()? {
+      Some(Token::Str(value)) => Value::Str(value),
+      Some(Token::Open) => Value::Obj(parse_entries(lexer, true)?),
+      Some(Token::Close) | None => return Err(lexer.err("missing value")),
+    };
+    entries.push((key, value));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse() {
+    let root = parse(
+      r#"
+      // comment
+      "root"
+      {
+        "a"   "1"
+        b     2 [$WIN32]
+        "obj" { "c" "3" }
+        "obj" { "c" "4" }
+      }
+      "#,
+    )
+    .unwrap();
+    let obj = root.get("Root").unwrap();
+    assert_eq!(obj.get("a").and_then(Value::as_str), Some("1"));
+    assert_eq!(obj.get("b").and_then(Value::as_str), Some("2"));
+    let cs: Vec<_> = obj
+      .entries()
+      .filter(|(k, _)| *k == "obj")
+      .filter_map(|(_, o)| o.get("c").and_then(Value::as_str))
+      .collect();
+    assert_eq!(cs, ["3", "4"]);
+
+    // round trip
+    assert_eq!(parse(&to_string(&root)).unwrap(), root);
+  }
+
+  #[test]
+  fn test_parse_error() {
+    assert_eq!(parse("\"a\" {\n\"b\" \"c\"").unwrap_err().line, 2);
+    assert!(parse("\"a\" }").is_err());
+    assert!(parse("\"a\"").is_err());
+    assert!(parse("\"a").is_err());
+  }
+}
@@ -1,46 +1,169 @@
 use log::{info, warn};
-use std::{io::Read, net::TcpStream, sync::mpsc, thread};
-use stickdeck_common::{Mouse, Packet, PACKET_FRAME_SIZE};
+use std::{
+  collections::HashMap,
+  io::{self, Read, Write},
+  net::{IpAddr, Shutdown, TcpListener, TcpStream},
+  sync::{mpsc, Arc, Mutex},
+  thread,
+  time::{Duration, Instant},
+};
+use stickdeck_common::{counter, perf, Keys, Mouse, Packet, Role, PACKET_FRAME_SIZE};
 use vigem_client::{XButtons, XGamepad};
 
 stickdeck_common::impl_deserializable_gamepad!(XGamepad, XButtons);
 
-pub fn spawn(server: &str, packet_tx: mpsc::SyncSender<Packet<XGamepad>>) {
-  info!("Connecting to {} ...", server);
+/// How often to send a timestamp to the server to measure the latency.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
 
-  let mut retry = 3;
-  let mut stream = loop {
-    if retry == 0 {
-      panic!("Failed to connect to the server: retry limit exceeded");
-    }
+/// Events from a server connection.
+#[derive(Debug)]
+pub enum Event {
+  /// Connected to the server with the address.
+  Connected(String),
+  Packet(Packet<XGamepad>),
+  /// The round-trip time to the server.
+  Latency(Duration),
+  /// Failed to connect, or disconnected. This is the last event of the server.
+  Disconnected,
+}
 
-    if let Ok(stream) = TcpStream::connect(server) {
-      break stream;
-    }
+/// Connect to the server in a new thread.
+/// Events are sent with the index of the server.
+pub fn spawn(index: usize, server: String, event_tx: mpsc::SyncSender<(usize, Event)>) {
+  thread::spawn(move || {
+    let send = |event| {
+      event_tx
+        .send((index, event))
+        .expect("Failed to send data to the main thread")
+    };
 
-    info!("Failed to connect to the server: retrying ...");
-    retry -= 1;
-  };
+    info!("Connecting to {} ...", server);
+
+    let mut retry = 3;
+    let stream = loop {
+      if retry == 0 {
+        warn!("Failed to connect to {}: retry limit exceeded", server);
+        send(Event::Disconnected);
+        return;
+      }
+
+      if let Ok(stream) = TcpStream::connect(&server) {
+        break stream;
+      }
 
-  info!("Connected");
+      info!("Failed to connect to {}: retrying ...", server);
+      retry -= 1;
+      thread::sleep(Duration::from_secs(1));
+    };
+
+    serve(index, &server, stream, &event_tx, None);
+  });
+}
+
+/// The id of the current connection of each server index in reverse mode.
+type Connections = Arc<Mutex<HashMap<usize, u64>>>;
+
+/// Reverse mode: wait for servers (`stickdeck --connect`) to connect in a new thread.
+/// Each server gets a new index, a server reconnecting from the same IP keeps its index.
+/// Return [`Err`] if the address can't be bound.
+pub fn listen(addr: &str, event_tx: mpsc::SyncSender<(usize, Event)>) -> io::Result<()> {
+  let listener = TcpListener::bind(addr)?;
+  info!("Listening on {}", listener.local_addr()?);
 
   thread::spawn(move || {
-    let mut buf = [0; PACKET_FRAME_SIZE];
-    while stream.read_exact(&mut buf).is_ok() {
-      match Packet::deserialize(&buf) {
-        Ok(packet) => {
-          packet_tx
-            .send(packet)
-            .expect("Failed to send data to the main thread");
-        }
-        Err(_) => {
-          warn!("Invalid packet: {:?}", buf);
+    let mut indexes = HashMap::<IpAddr, usize>::new();
+    let connections = Connections::default();
+    for (id, stream) in (0..).zip(listener.incoming()) {
+      let stream = match stream.and_then(|s| s.peer_addr().map(|addr| (s, addr))) {
+        Ok(accepted) => accepted,
+        Err(e) => {
+          warn!("Failed to accept connection: {}", e);
+          continue;
         }
+      };
+      let (stream, addr) = stream;
+      let next = indexes.len();
+      let index = *indexes.entry(addr.ip()).or_insert(next);
+      // a reconnecting server supersedes its old connection, whose events are dropped
+      connections.lock().unwrap().insert(index, id);
+      let event_tx = event_tx.clone();
+      let current = Some((connections.clone(), id));
+      thread::spawn(move || serve(index, &addr.to_string(), stream, &event_tx, current));
+    }
+  });
+
+  Ok(())
+}
+
+/// Read packets from the connected server until it's disconnected.
+/// In reverse mode, `current` is the connections and the id of this one.
+fn serve(
+  index: usize,
+  server: &str,
+  mut stream: TcpStream,
+  event_tx: &mpsc::SyncSender<(usize, Event)>,
+  current: Option<(Connections, u64)>,
+) {
+  let send = |event| {
+    // hold the lock while sending, so events of a newer connection can't come first
+    let connections = current.as_ref().map(|(c, _)| c.lock().unwrap());
+    if let (Some(connections), Some((_, id))) = (&connections, &current) {
+      if connections.get(&index) != Some(id) {
+        return;
       }
     }
+    event_tx
+      .send((index, event))
+      .expect("Failed to send data to the main thread")
+  };
 
-    info!("Disconnected");
-  });
+  info!("Connected to {}", server);
+  send(Event::Connected(server.to_string()));
+
+  // timestamps are relative to this instant, and echoed back by the server
+  let start = Instant::now();
+  match stream.try_clone() {
+    Ok(mut writer) => {
+      thread::spawn(move || ping(&mut writer, start));
+    }
+    Err(e) => warn!("Failed to clone the socket: {}", e),
+  }
+
+  let mut buf = [0; PACKET_FRAME_SIZE];
+  while stream.read_exact(&mut buf).is_ok() {
+    counter!("packets received").add(1);
+    counter!("bytes received").add(PACKET_FRAME_SIZE as u64);
+    match perf!("decode", Packet::deserialize(&buf), 1) {
+      Ok(Packet::Timestamp(timestamp)) => send(Event::Latency(
+        start
+          .elapsed()
+          .saturating_sub(Duration::from_micros(timestamp)),
+      )),
+      Ok(packet) => send(Event::Packet(packet)),
+      Err(_) => {
+        warn!("Invalid packet: {:?}", buf);
+        counter!("invalid packets").add(1);
+      }
+    }
+  }
+
+  // stop the ping thread
+  stream.shutdown(Shutdown::Both).ok();
+  info!("Disconnected from {}", server);
+  send(Event::Disconnected);
+}
+
+/// Send timestamps in microseconds since `start` to the server, until the socket is closed.
+fn ping(stream: &mut TcpStream, start: Instant) {
+  let mut buf = [0; PACKET_FRAME_SIZE];
+  loop {
+    let timestamp = start.elapsed().as_micros() as u64;
+    buf[1..9].copy_from_slice(&timestamp.to_le_bytes());
+    if stream.write_all(&buf).is_err() {
+      break;
+    }
+    thread::sleep(PING_INTERVAL);
+  }
 }
 
 trait DeserializablePacket {
@@ -61,8 +184,15 @@ impl<Gamepad: DeserializableGamepad<Target = Gamepad>> DeserializablePacket for
         let timestamp = u64::from_le_bytes(buf[1..9].try_into().unwrap());
         Ok(Packet::Timestamp(timestamp))
       }
-      1 => Ok(Packet::Gamepad(Gamepad::deserialize(&buf[1..]))),
+      1 => Ok(Packet::Gamepad(buf[1], Gamepad::deserialize(&buf[2..]))),
       2 => Ok(Packet::Mouse(Mouse::deserialize(&buf[1..]))),
+      3 => Ok(Packet::PadRemoved(buf[1])),
+      4 => match buf[1] {
+        0 => Ok(Packet::Role(Role::Controller)),
+        1 => Ok(Packet::Role(Role::Observer)),
+        _ => Err(buf[0]),
+      },
+      5 => Ok(Packet::Keys(Keys(buf[1]))),
       _ => Err(buf[0]),
     }
   }
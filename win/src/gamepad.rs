@@ -1,32 +1,77 @@
+use log::info;
+use std::{collections::HashMap, rc::Rc};
 use vigem_client::{Client, TargetId, XGamepad, Xbox360Wired};
 
+/// Virtual controllers indexed by the server index and the pad index.
+/// Controllers are plugged in on demand and unplugged when the pad is removed.
 pub struct GamepadController {
-  xbox: Xbox360Wired<Client>,
+  client: Rc<Client>,
+  pads: HashMap<(usize, u8), Xbox360Wired<Rc<Client>>>,
 }
 
 impl GamepadController {
   pub fn new() -> Self {
-    let mut xbox = Xbox360Wired::new(
-      Client::connect().expect("Failed to connect to the ViGEmBus driver"),
-      TargetId::XBOX360_WIRED,
-    );
+    Self {
+      client: Rc::new(Client::connect().expect("Failed to connect to the ViGEmBus driver")),
+      pads: HashMap::new(),
+    }
+  }
+
+  /// Plug in a virtual controller for the pad if it's not plugged in.
+  /// Windows assigns controller slots in the order they are plugged in.
+  pub fn plugin(&mut self, server: usize, pad: u8) -> &mut Xbox360Wired<Rc<Client>> {
+    self.pads.entry((server, pad)).or_insert_with(|| {
+      let mut xbox = Xbox360Wired::new(self.client.clone(), TargetId::XBOX360_WIRED);
 
-    xbox
-      .plugin()
-      .expect("Failed to plugin the virtual controller");
+      xbox
+        .plugin()
+        .expect("Failed to plugin the virtual controller");
 
-    xbox
-      .wait_ready()
-      .expect("Failed to wait for the virtual controller to be ready");
+      xbox
+        .wait_ready()
+        .expect("Failed to wait for the virtual controller to be ready");
 
-    Self { xbox }
+      info!(
+        "Virtual controller for server {} pad {} is ready",
+        server + 1,
+        pad + 1
+      );
+      xbox
+    })
   }
 
-  /// Apply the gamepad state.
-  pub fn apply(&mut self, data: &XGamepad) {
+  /// Apply the gamepad state to the pad. Plug in a virtual controller if the pad is new.
+  pub fn apply(&mut self, server: usize, pad: u8, data: &XGamepad) {
     self
-      .xbox
+      .plugin(server, pad)
       .update(data)
       .expect("Failed to update the virtual controller")
   }
+
+  /// Unplug the virtual controller of the pad.
+  pub fn remove(&mut self, server: usize, pad: u8) {
+    if let Some(mut xbox) = self.pads.remove(&(server, pad)) {
+      xbox
+        .unplug()
+        .expect("Failed to unplug the virtual controller");
+      info!(
+        "Virtual controller for server {} pad {} is unplugged",
+        server + 1,
+        pad + 1
+      );
+    }
+  }
+
+  /// Unplug all virtual controllers of the server.
+  pub fn remove_server(&mut self, server: usize) {
+    let pads: Vec<_> = self
+      .pads
+      .keys()
+      .filter(|(s, _)| *s == server)
+      .copied()
+      .collect();
+    for (server, pad) in pads {
+      self.remove(server, pad);
+    }
+  }
 }
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use stickdeck_common::Keys;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+  SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_EXTENDEDKEY,
+  KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, VIRTUAL_KEY,
+};
+
+/// Press the keys sent by the servers.
+/// A key is held while any server presses it.
+pub struct KeyboardController {
+  servers: HashMap<usize, Keys>,
+  pressed: Keys,
+}
+
+impl KeyboardController {
+  const INPUT_SIZE: i32 = std::mem::size_of::<INPUT>() as i32;
+
+  pub fn new() -> Self {
+    Self {
+      servers: HashMap::new(),
+      pressed: Keys::default(),
+    }
+  }
+
+  /// Apply the keys of a server.
+  pub fn apply(&mut self, server: usize, keys: Keys) {
+    self.servers.insert(server, keys);
+    self.update();
+  }
+
+  /// Release the keys of a server, e.g. when it's disconnected.
+  pub fn remove_server(&mut self, server: usize) {
+    if self.servers.remove(&server).is_some() {
+      self.update();
+    }
+  }
+
+  fn update(&mut self) {
+    let pressed = Keys(self.servers.values().fold(0, |all, keys| all | keys.0));
+    let inputs: Vec<_> = Keys::ALL
+      .into_iter()
+      .filter(|&key| pressed.is_pressed(key) != self.pressed.is_pressed(key))
+      .map(|key| key_input(key, pressed.is_pressed(key)))
+      .collect();
+    self.pressed = pressed;
+    if !inputs.is_empty() {
+      unsafe { SendInput(&inputs, Self::INPUT_SIZE) };
+    }
+  }
+}
+
+/// A key event with the scan code, which games reading raw input or DirectInput also receive.
+fn key_input(key: u8, down: bool) -> INPUT {
+  // (scan code, extended)
+  let (scan, extended) = match key {
+    Keys::W => (0x11, false),
+    Keys::A => (0x1E, false),
+    Keys::S => (0x1F, false),
+    Keys::D => (0x20, false),
+    Keys::UP => (0x48, true),
+    Keys::DOWN => (0x50, true),
+    Keys::LEFT => (0x4B, true),
+    _ => (0x4D, true),
+  };
+  let mut flags = KEYEVENTF_SCANCODE;
+  if extended {
+    flags |= KEYEVENTF_EXTENDEDKEY;
+  }
+  if !down {
+    flags |= KEYEVENTF_KEYUP;
+  }
+  INPUT {
+    r#type: INPUT_KEYBOARD,
+    Anonymous: INPUT_0 {
+      ki: KEYBDINPUT {
+        wVk: VIRTUAL_KEY(0),
+        wScan: scan,
+        dwFlags: KEYBD_EVENT_FLAGS(flags.0),
+        time: 0,
+        dwExtraInfo: 0,
+      },
+    },
+  }
+}
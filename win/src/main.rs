@@ -1,24 +1,50 @@
 mod client;
 mod gamepad;
+mod keyboard;
 mod mouse;
+mod status;
 
-use crate::{gamepad::GamepadController, mouse::MouseController};
+use crate::{
+  client::Event,
+  gamepad::GamepadController,
+  keyboard::KeyboardController,
+  mouse::MouseController,
+  status::{State, Status},
+};
 use clap::Parser;
-use log::{debug, info, log_enabled, trace, Level};
-use std::{env, sync::mpsc, time::Instant};
-use stickdeck_common::{perf, Packet};
+use log::{debug, error, info, log_enabled, trace, warn, Level};
+use std::{
+  collections::{BTreeMap, HashMap},
+  env, process,
+  sync::mpsc::{self, RecvTimeoutError},
+  time::{Duration, Instant},
+};
+use stickdeck_common::{metrics, perf, with_port, ConnectUri, Packet, Role, PROTOCOL_VERSION};
+
+/// How often to print the status of all servers.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+/// How often to print the metrics in debug mode.
+const METRICS_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Turn your Steam Deck into a joystick for your PC, with trackpad and gyro support!
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-  /// Server address to connect to
+  /// Server addresses to connect to, as `host`, `host:port`, an IPv6 address, `[IPv6]:port`
+  /// or a `stickdeck://` URI from the server's QR code.
+  /// Virtual controllers are plugged in in this order, so the first server is player 1
   #[arg(default_value = "steamdeck")]
-  server: String,
+  servers: Vec<String>,
 
-  /// Server port to connect to
+  /// Server port to connect to, for servers without a port.
+  /// In reverse mode, the port to listen on
   #[arg(short, long, default_value = "7777")]
   port: u16,
+
+  /// Reverse mode: wait for servers (`stickdeck --connect`) to connect to this PC
+  /// instead of connecting to them. Servers are numbered in the order they connect
+  #[arg(long, conflicts_with = "servers")]
+  listen: bool,
 }
 
 fn main() {
@@ -32,37 +58,153 @@ fn main() {
   info!("stickdeck-win v{}", clap::crate_version!());
   info!("See https://github.com/DiscreteTom/stickdeck-rs for more info.");
 
-  let (packet_tx, packet_rx) = mpsc::sync_channel(8);
-
-  // connect to the server
-  client::spawn(&format!("{}:{}", args.server, args.port), packet_tx);
-
   let mut gamepad = GamepadController::new();
-  info!("Virtual controller is ready");
-
   let mut mouse = MouseController::new();
+  let mut keyboard = KeyboardController::new();
+
+  let (event_tx, event_rx) = mpsc::sync_channel(8);
+  let mut statuses = BTreeMap::new();
+  // the last state of the pads of observed servers, applied when a server becomes the controller
+  let mut observed = HashMap::new();
 
-  let mut now = Instant::now();
-  let mut count = 0;
+  if args.listen {
+    client::listen(&format!("0.0.0.0:{}", args.port), event_tx)
+      .expect("Failed to listen for servers");
+  } else {
+    for (i, server) in args.servers.iter().enumerate() {
+      let server = server_addr(server, args.port).unwrap_or_else(|e| {
+        error!("{}", e);
+        process::exit(1);
+      });
+      statuses.insert(i, Status::new(server.clone()));
+      // plug in the first pad of each server in order, so the controller slots follow the server order
+      gamepad.plugin(i, 0);
+      client::spawn(i, server, event_tx.clone());
+    }
+    // exit when all connections are closed
+    drop(event_tx);
+  }
 
-  while let Ok(data) = packet_rx.recv() {
-    trace!("Got {:?}", data);
+  let mut last_status = Instant::now();
+  let mut last_metrics = Instant::now();
+  let mut last_packets = 0;
 
-    match data {
-      Packet::Timestamp(_timestamp) => {} // TODO
-      Packet::Gamepad(data) => perf!("update gamepad", gamepad.apply(&data), 10),
-      Packet::Mouse(data) => perf!("move mouse", mouse.apply(&data), 10),
+  loop {
+    if last_status.elapsed() >= STATUS_INTERVAL {
+      print_status(&statuses, &mut last_status);
+    }
+    if log_enabled!(Level::Debug) && last_metrics.elapsed() >= METRICS_INTERVAL {
+      print_metrics(&mut last_metrics, &mut last_packets);
     }
 
-    if log_enabled!(Level::Debug) {
-      count += 1;
-      if now.elapsed().as_secs() >= 1 {
-        debug!("{} updates per second", count);
-        now = Instant::now();
-        count = 0;
+    let (server, event) = match event_rx.recv_timeout(METRICS_INTERVAL) {
+      Ok(e) => e,
+      Err(RecvTimeoutError::Timeout) => continue,
+      Err(RecvTimeoutError::Disconnected) => break,
+    };
+    trace!("Got {:?} from server {}", event, server + 1);
+
+    let status = statuses
+      .entry(server)
+      .or_insert_with(|| Status::new(String::new()));
+    let controller = status.role == Role::Controller;
+    match event {
+      Event::Packet(Packet::Timestamp(_)) => {} // handled by the client
+      Event::Packet(Packet::Role(role)) => {
+        info!("[Server {}] Role: {:?}", server + 1, role);
+        status.role = role;
+        match role {
+          // plug in the pads which the server has sent, not a fixed one
+          Role::Controller => {
+            let pads: Vec<_> = observed
+              .keys()
+              .filter(|(s, _)| *s == server)
+              .copied()
+              .collect();
+            for (server, pad) in pads {
+              if let Some(data) = observed.remove(&(server, pad)) {
+                gamepad.apply(server, pad, &data);
+              }
+            }
+          }
+          // release everything
+          Role::Observer => {
+            gamepad.remove_server(server);
+            keyboard.remove_server(server);
+            if controller {
+              mouse.release_buttons();
+            }
+          }
+        }
+      }
+      // observers receive the input but don't apply it
+      Event::Packet(Packet::Gamepad(pad, data)) if !controller => {
+        observed.insert((server, pad), data);
+      }
+      Event::Packet(Packet::PadRemoved(pad)) if !controller => {
+        observed.remove(&(server, pad));
+      }
+      Event::Packet(Packet::Mouse(_) | Packet::Keys(_)) if !controller => {}
+      Event::Packet(Packet::Gamepad(pad, data)) => {
+        perf!("update gamepad", gamepad.apply(server, pad, &data), 10)
+      }
+      Event::Packet(Packet::Mouse(data)) => perf!("move mouse", mouse.apply(&data), 10),
+      Event::Packet(Packet::Keys(keys)) => perf!("press keys", keyboard.apply(server, keys), 10),
+      Event::Packet(Packet::PadRemoved(pad)) => gamepad.remove(server, pad),
+      Event::Connected(addr) => {
+        status.server = addr;
+        status.state = State::Connected;
+        print_status(&statuses, &mut last_status);
+      }
+      Event::Latency(latency) => status.latency = Some(latency),
+      Event::Disconnected => {
+        status.state = State::Disconnected;
+        status.latency = None;
+        observed.retain(|(s, _), _| *s != server);
+        gamepad.remove_server(server);
+        keyboard.remove_server(server);
+        if controller {
+          mouse.release_buttons();
+        }
+        print_status(&statuses, &mut last_status);
       }
     }
   }
 
   info!("Shutting down...");
 }
+
+/// Get the address from a server argument, which is an address or a `stickdeck://` URI.
+/// Return [`Err`] if the URI is invalid.
+fn server_addr(server: &str, port: u16) -> Result<String, String> {
+  if !ConnectUri::is_uri(server) {
+    return Ok(with_port(server, port));
+  }
+  let uri: ConnectUri = server.parse()?;
+  if let Some(version) = uri.version.filter(|&v| v != PROTOCOL_VERSION) {
+    warn!(
+      "{} uses protocol version {}, but this client uses version {}. Update the older one",
+      uri.addr, version, PROTOCOL_VERSION
+    );
+  }
+  Ok(with_port(&uri.addr, port))
+}
+
+fn print_status(statuses: &BTreeMap<usize, Status>, last_status: &mut Instant) {
+  for (i, status) in statuses.iter() {
+    info!("[Server {}] {}", i + 1, status);
+  }
+  *last_status = Instant::now();
+}
+
+fn print_metrics(last_metrics: &mut Instant, last_packets: &mut u64) {
+  let snapshot = metrics::snapshot();
+  let packets = snapshot.counter("packets received").unwrap_or(0);
+  debug!(
+    "{:.1} packets per second, metrics:\n{}",
+    (packets - *last_packets) as f64 / last_metrics.elapsed().as_secs_f64(),
+    snapshot
+  );
+  *last_packets = packets;
+  *last_metrics = Instant::now();
+}
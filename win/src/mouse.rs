@@ -75,4 +75,10 @@ impl MouseController {
       SendInput(&[input], Self::INPUT_SIZE);
     }
   }
+  /// Release the held buttons, e.g. when the server stops controlling the mouse.
+  pub fn release_buttons(&mut self) {
+    if self.last_button_state != MouseButton::default() {
+      self.apply(&Mouse::default());
+    }
+  }
 }
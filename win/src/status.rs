@@ -0,0 +1,46 @@
+use std::{fmt, time::Duration};
+use stickdeck_common::Role;
+
+/// Connection status of a server, shown in the status lines.
+pub struct Status {
+  pub server: String,
+  pub state: State,
+  /// Observers don't apply the input. Servers without roles only have controllers.
+  pub role: Role,
+  /// The last measured round-trip time. [`None`] if the server doesn't echo timestamps.
+  pub latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+  Connecting,
+  Connected,
+  Disconnected,
+}
+
+impl Status {
+  pub fn new(server: String) -> Self {
+    Self {
+      server,
+      state: State::Connecting,
+      role: Role::Controller,
+      latency: None,
+    }
+  }
+}
+
+impl fmt::Display for Status {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {:?}", self.server, self.state)?;
+    match (self.state, self.latency) {
+      (State::Connected, Some(latency)) => write!(
+        f,
+        " as {:?}, latency {:.1}ms",
+        self.role,
+        latency.as_secs_f64() * 1000.0
+      ),
+      (State::Connected, None) => write!(f, " as {:?}, latency unknown", self.role),
+      _ => Ok(()),
+    }
+  }
+}